@@ -6,21 +6,225 @@
 /// never constructed, and is only accessed in a static context.
 /// 
 /// ```
+/// # use PotatoNeuralNet::{ActivationFunction, ConnectedGenericLayer, InputLayer};
+/// # use std::{cell::RefCell, rc::Rc};
 /// struct LinearActivation;
 /// impl ActivationFunction for LinearActivation {
+///     fn name() -> &'static str {
+///         "clamped_linear"
+///     }
 ///     fn activate(f_in: f32) -> f32 {
-///         if (f_in < -1.0) {
+///         if f_in < -1.0 {
 ///             -1.0
-///         } else if (f_in > 1.0) {
+///         } else if f_in > 1.0 {
 ///             1.0
 ///         }else {
 ///             f_in
 ///         }
 ///     }
 /// }
+/// # let input = Rc::new(RefCell::new(InputLayer::new(Rc::new(RefCell::new([0.0; 4])))));
 /// // declaring the ActivationFunction...
-/// let layer : ConnectedGenericLayer<_, LinearActivation, SIZE, SIZE_PREV> = ConnectedGenericLayer::new(...);
+/// let layer : ConnectedGenericLayer<_, LinearActivation, 2, 4> = ConnectedGenericLayer::new(input);
 /// ```
 pub trait ActivationFunction {
     fn activate(f_in : f32) -> f32;
-}
\ No newline at end of file
+    /// Identifies the activation function in saved models, so a model can't be loaded into a
+    /// layer with a different activation. Must stay the same across versions of your code,
+    /// or models saved before the change won't load.
+    ///
+    /// Defaults to the path of the type, which changes when the type is renamed or moved (and
+    /// isn't promised to be the same across compiler versions), so override it for models
+    /// that are kept around.
+    ///
+    /// ```
+    /// # use PotatoNeuralNet::ActivationFunction;
+    /// struct Cube;
+    /// impl ActivationFunction for Cube {
+    ///     fn activate(f_in: f32) -> f32 {
+    ///         f_in * f_in * f_in
+    ///     }
+    /// }
+    /// assert!(Cube::name().ends_with("Cube"));
+    /// ```
+    fn name() -> &'static str {
+        std::any::type_name::<Self>()
+    }
+    /// The slope of `activate` at `f_in`, used during backpropagation.
+    ///
+    /// Defaults to a central difference approximation, so hand written activation functions
+    /// can be trained without deriving it. The built in activation functions provide the
+    /// exact derivative.
+    fn derivative(f_in : f32) -> f32 {
+        const H : f32 = 1e-3;
+        (Self::activate(f_in + H) - Self::activate(f_in - H)) / (2.0 * H)
+    }
+}
+
+/// Passes the signal through unchanged.
+#[derive(Clone)]
+pub struct Linear;
+impl ActivationFunction for Linear {
+    fn name() -> &'static str {
+        "linear"
+    }
+    fn activate(f_in : f32) -> f32 {
+        f_in
+    }
+    fn derivative(_f_in : f32) -> f32 {
+        1.0
+    }
+}
+
+/// The logistic function, `1 / (1 + e^-x)`.
+#[derive(Clone)]
+pub struct Sigmoid;
+impl ActivationFunction for Sigmoid {
+    fn name() -> &'static str {
+        "sigmoid"
+    }
+    fn activate(f_in : f32) -> f32 {
+        1.0 / (1.0 + (-f_in).exp())
+    }
+    fn derivative(f_in : f32) -> f32 {
+        let s = Self::activate(f_in);
+        s * (1.0 - s)
+    }
+}
+
+/// The hyperbolic tangent.
+#[derive(Clone)]
+pub struct Tanh;
+impl ActivationFunction for Tanh {
+    fn name() -> &'static str {
+        "tanh"
+    }
+    fn activate(f_in : f32) -> f32 {
+        f_in.tanh()
+    }
+    fn derivative(f_in : f32) -> f32 {
+        let t = f_in.tanh();
+        1.0 - t * t
+    }
+}
+
+/// Rectified linear unit, `max(0, x)`.
+#[derive(Clone)]
+pub struct Relu;
+impl ActivationFunction for Relu {
+    fn name() -> &'static str {
+        "relu"
+    }
+    fn activate(f_in : f32) -> f32 {
+        f_in.max(0.0)
+    }
+    fn derivative(f_in : f32) -> f32 {
+        if f_in > 0.0 { 1.0 } else { 0.0 }
+    }
+}
+
+/// Rectified linear unit with a slope of 0.01 for negative inputs.
+#[derive(Clone)]
+pub struct LeakyRelu;
+impl LeakyRelu {
+    pub const SLOPE : f32 = 0.01;
+}
+impl ActivationFunction for LeakyRelu {
+    fn name() -> &'static str {
+        "leaky_relu"
+    }
+    fn activate(f_in : f32) -> f32 {
+        if f_in > 0.0 { f_in } else { f_in * Self::SLOPE }
+    }
+    fn derivative(f_in : f32) -> f32 {
+        if f_in > 0.0 { 1.0 } else { Self::SLOPE }
+    }
+}
+
+/// Exponential linear unit with `alpha = 1`, `e^x - 1` for negative inputs.
+#[derive(Clone)]
+pub struct Elu;
+impl Elu {
+    pub const ALPHA : f32 = 1.0;
+}
+impl ActivationFunction for Elu {
+    fn name() -> &'static str {
+        "elu"
+    }
+    fn activate(f_in : f32) -> f32 {
+        if f_in > 0.0 { f_in } else { Self::ALPHA * f_in.exp_m1() }
+    }
+    fn derivative(f_in : f32) -> f32 {
+        if f_in > 0.0 { 1.0 } else { Self::ALPHA * f_in.exp() }
+    }
+}
+
+/// Gaussian error linear unit, using the usual tanh approximation.
+#[derive(Clone)]
+pub struct Gelu;
+impl Gelu {
+    // sqrt(2 / pi)
+    const C : f32 = 0.797_884_6;
+    const K : f32 = 0.044_715;
+}
+impl ActivationFunction for Gelu {
+    fn name() -> &'static str {
+        "gelu"
+    }
+    fn activate(f_in : f32) -> f32 {
+        let t = (Self::C * (f_in + Self::K * f_in * f_in * f_in)).tanh();
+        0.5 * f_in * (1.0 + t)
+    }
+    fn derivative(f_in : f32) -> f32 {
+        let t = (Self::C * (f_in + Self::K * f_in * f_in * f_in)).tanh();
+        let dt = Self::C * (1.0 + 3.0 * Self::K * f_in * f_in) * (1.0 - t * t);
+        0.5 * (1.0 + t) + 0.5 * f_in * dt
+    }
+}
+
+/// A smooth approximation of the rectifier, `ln(1 + e^x)`.
+#[derive(Clone)]
+pub struct Softplus;
+impl ActivationFunction for Softplus {
+    fn name() -> &'static str {
+        "softplus"
+    }
+    fn activate(f_in : f32) -> f32 {
+        // written this way to avoid overflowing e^x for large inputs
+        f_in.max(0.0) + (-f_in.abs()).exp().ln_1p()
+    }
+    fn derivative(f_in : f32) -> f32 {
+        Sigmoid::activate(f_in)
+    }
+}
+
+/// Self gated activation, `x * sigmoid(x)`.
+#[derive(Clone)]
+pub struct Swish;
+impl ActivationFunction for Swish {
+    fn name() -> &'static str {
+        "swish"
+    }
+    fn activate(f_in : f32) -> f32 {
+        f_in * Sigmoid::activate(f_in)
+    }
+    fn derivative(f_in : f32) -> f32 {
+        let s = Sigmoid::activate(f_in);
+        s + f_in * s * (1.0 - s)
+    }
+}
+
+/// Clamps the signal to `[-1, 1]`.
+#[derive(Clone)]
+pub struct HardTanh;
+impl ActivationFunction for HardTanh {
+    fn name() -> &'static str {
+        "hard_tanh"
+    }
+    fn activate(f_in : f32) -> f32 {
+        f_in.clamp(-1.0, 1.0)
+    }
+    fn derivative(f_in : f32) -> f32 {
+        if (-1.0..=1.0).contains(&f_in) { 1.0 } else { 0.0 }
+    }
+}
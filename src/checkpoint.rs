@@ -0,0 +1,301 @@
+use crate::data_set::{Dataset, DatasetLoader, DatasetState, Datum};
+use crate::layers::Layer;
+use crate::model_info::ModelInformation;
+use crate::optimizer::Optimizer;
+use crate::serialization::{ModelError, ModelFile, MODEL_FORMAT_VERSION};
+use crate::training::TrainingHistory;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+/// Everything needed to resume a training run exactly where it stopped.
+///
+/// The `ModelInformation` saved with the model carries the position in the learning rate
+/// schedule, and the dataset state carries the training order, cursors and random generator.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub version : u32,
+    /// The last epoch that was completed.
+    pub epoch : usize,
+    /// Lower is better, e.g. the validation loss. Used to keep the best checkpoint.
+    pub metric : Option<f32>,
+    pub model : ModelFile,
+    pub optimizer : serde_json::Value,
+    pub dataset : DatasetState,
+    pub history : TrainingHistory,
+}
+impl Checkpoint {
+    pub fn capture<L, O, D, Ld, const SIZE : usize>(
+        epoch : usize,
+        metric : Option<f32>,
+        layer : &L,
+        info : ModelInformation,
+        optimizer : &O,
+        data : &Dataset<D, Ld, SIZE>,
+        history : &TrainingHistory,
+    ) -> Checkpoint
+    where
+        L : Layer,
+        O : Optimizer,
+        D : Datum<SIZE>,
+        Ld : DatasetLoader<D, SIZE>,
+    {
+        Checkpoint {
+            version: MODEL_FORMAT_VERSION,
+            epoch,
+            metric,
+            model: ModelFile::from_layer(layer, info),
+            optimizer: optimizer.save_state(),
+            dataset: data.get_state(),
+            history: history.clone(),
+        }
+    }
+    /// Loads the checkpoint into the model, optimizer and dataset of a fresh run, and returns
+    /// the `ModelInformation` to continue with.
+    ///
+    /// The dataset gets the saved split back, even if it was built unseeded and split its
+    /// samples differently.
+    ///
+    /// ```
+    /// # use PotatoNeuralNet::{Checkpoint, ClassifiedData, ConnectedGenericLayer, Dataset, DatasetLoader, Datum, FileError, InputLayer, Linear, ModelInformation, Sgd, TrainingHistory};
+    /// # use std::{cell::RefCell, collections::BTreeSet, rc::Rc};
+    /// # #[derive(Copy, Clone)]
+    /// # struct Value(f32);
+    /// # impl Datum<1> for Value {
+    /// #     type DataType = f32;
+    /// #     type ReceiverType = Rc<RefCell<[f32; 1]>>;
+    /// #     fn from(data: Vec<u8>) -> Option<Self> { Some(Value(*data.first()? as f32)) }
+    /// #     fn seed(&self, receiver: Self::ReceiverType) { receiver.borrow_mut()[0] = self.0; }
+    /// # }
+    /// # struct Counter(u8);
+    /// # impl DatasetLoader<Value, 1> for Counter {
+    /// #     fn next(&mut self) -> Result<ClassifiedData<Value, 1>, FileError> {
+    /// #         self.0 += 1;
+    /// #         Ok(ClassifiedData::new(Value(self.0 as f32), (self.0 % 2) as f32))
+    /// #     }
+    /// #     fn has_next(&self) -> bool { self.0 < 100 }
+    /// # }
+    /// # let input = Rc::new(RefCell::new(InputLayer::new(Rc::new(RefCell::new([0.0; 1])))));
+    /// let mut layer : ConnectedGenericLayer<_, Linear, 1, 1> = ConnectedGenericLayer::new(input);
+    /// let mut optimizer = Sgd::new();
+    /// let data = Dataset::<Value, _, 1>::new(Counter(0), 0.3);
+    /// # let path = std::env::temp_dir().join(format!("restore_doctest_{}.json", std::process::id()));
+    /// Checkpoint::capture(0, None, &layer, ModelInformation::new(0.1, 1.0), &optimizer, &data, &TrainingHistory::new())
+    ///     .save(&path).unwrap();
+    ///
+    /// // unseeded, so split differently
+    /// let resumed = Dataset::<Value, _, 1>::new(Counter(0), 0.3);
+    /// Checkpoint::load(&path).unwrap().restore(&mut layer, &mut optimizer, &resumed).unwrap();
+    /// # std::fs::remove_file(&path).unwrap();
+    /// assert_eq!(resumed.get_training_indices(), data.get_training_indices());
+    /// assert_eq!(resumed.get_validation_indices(), data.get_validation_indices());
+    /// let training : BTreeSet<usize> = resumed.get_training_indices().into_iter().collect();
+    /// assert!(resumed.get_validation_indices().iter().all(|i| !training.contains(i)));
+    /// ```
+    pub fn restore<L, O, D, Ld, const SIZE : usize>(&self, layer : &mut L, optimizer : &mut O, data : &Dataset<D, Ld, SIZE>) -> Result<ModelInformation, ModelError>
+    where
+        L : Layer,
+        O : Optimizer,
+        D : Datum<SIZE>,
+        Ld : DatasetLoader<D, SIZE>,
+    {
+        let info = self.model.apply(layer)?;
+        optimizer.load_state(self.optimizer.clone())?;
+        if !data.restore_state(&self.dataset) {
+            return Err(ModelError::ArchitectureMismatch("the dataset has different samples than the checkpoint".into()));
+        }
+        Ok(info)
+    }
+
+    /// Writes the checkpoint to `path`. Fails without writing anything if a number in it is
+    /// NaN or infinite, e.g. after the run diverged: JSON would write it as `null`, and the
+    /// checkpoint couldn't be loaded back.
+    ///
+    /// ```
+    /// # use PotatoNeuralNet::{Adam, Checkpoint, CheckpointManager, ClassifiedData, ConnectedGenericLayer, Dataset, DatasetLoader, Datum, EpochRecord, Evaluation, FileError, InputLayer, Linear, ModelError, ModelInformation, Optimizer, ParameterKind, TrainingHistory};
+    /// # use std::{cell::RefCell, rc::Rc};
+    /// # #[derive(Copy, Clone)]
+    /// # struct Value(f32);
+    /// # impl Datum<1> for Value {
+    /// #     type DataType = f32;
+    /// #     type ReceiverType = Rc<RefCell<[f32; 1]>>;
+    /// #     fn from(data: Vec<u8>) -> Option<Self> { Some(Value(*data.first()? as f32)) }
+    /// #     fn seed(&self, receiver: Self::ReceiverType) { receiver.borrow_mut()[0] = self.0; }
+    /// # }
+    /// # struct Counter(u8);
+    /// # impl DatasetLoader<Value, 1> for Counter {
+    /// #     fn next(&mut self) -> Result<ClassifiedData<Value, 1>, FileError> {
+    /// #         self.0 += 1;
+    /// #         Ok(ClassifiedData::new(Value(self.0 as f32), (self.0 % 2) as f32))
+    /// #     }
+    /// #     fn has_next(&self) -> bool { self.0 < 10 }
+    /// # }
+    /// # let input = Rc::new(RefCell::new(InputLayer::new(Rc::new(RefCell::new([0.0; 1])))));
+    /// # let dir = std::env::temp_dir().join(format!("save_doctest_{}", std::process::id()));
+    /// let layer : ConnectedGenericLayer<_, Linear, 1, 1> = ConnectedGenericLayer::new(input);
+    /// let info = ModelInformation::new(0.1, 1.0);
+    /// let mut optimizer = Adam::new();
+    /// let data = Dataset::<Value, _, 1>::new(Counter(0), 0.3);
+    /// let mut history = TrainingHistory::new();
+    /// let checkpoints = CheckpointManager::new(&dir).unwrap();
+    /// checkpoints.save(&Checkpoint::capture(0, Some(0.4), &layer, info, &optimizer, &data, &history)).unwrap();
+    ///
+    /// // the run diverges
+    /// let validation = Evaluation { loss: f32::NAN, accuracy: 0.0, samples: 3 };
+    /// history.record(EpochRecord { epoch: 1, learning_rate: 0.1, training_loss: f32::NAN, validation });
+    /// let diverged = Checkpoint::capture(1, None, &layer, info, &optimizer, &data, &history);
+    /// assert!(matches!(checkpoints.save(&diverged), Err(ModelError::NonFiniteValues(_))));
+    /// optimizer.begin_step();
+    /// optimizer.step(&mut [0.5], &[f32::NAN], ParameterKind::Weight, 0.1);
+    /// let diverged = Checkpoint::capture(1, None, &layer, info, &optimizer, &data, &TrainingHistory::new());
+    /// assert!(matches!(checkpoints.save(&diverged), Err(ModelError::NonFiniteValues(_))));
+    ///
+    /// // the last good checkpoint can still be resumed from
+    /// let latest = checkpoints.latest().unwrap().unwrap();
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// assert_eq!((latest.epoch, latest.metric), (0, Some(0.4)));
+    /// ```
+    pub fn save<P : AsRef<Path>>(&self, path : P) -> Result<(), ModelError> {
+        // write then rename, so an interrupted save never leaves a half written checkpoint
+        let path = path.as_ref();
+        self.check_finite()?;
+        let tmp = path.with_extension("tmp");
+        File::create(&tmp)?.write_all(serde_json::to_string(self).unwrap().as_bytes())?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+    fn check_finite(&self) -> Result<(), ModelError> {
+        self.model.check_finite()?;
+        if self.metric.is_some_and(|m| !m.is_finite()) {
+            return Err(ModelError::NonFiniteValues("the checkpoint metric".into()));
+        }
+        let mut history = self.history.get_epochs().iter()
+            .flat_map(|r| [r.learning_rate, r.training_loss, r.validation.loss, r.validation.accuracy]);
+        if !history.all(f32::is_finite) {
+            return Err(ModelError::NonFiniteValues("the training history".into()));
+        }
+        // the optimizer state is already JSON, where NaN and infinite numbers became null
+        if has_null(&self.optimizer) {
+            return Err(ModelError::NonFiniteValues("the optimizer state".into()));
+        }
+        Ok(())
+    }
+    pub fn load<P : AsRef<Path>>(path : P) -> Result<Checkpoint, ModelError> {
+        let mut json = String::new();
+        File::open(path)?.read_to_string(&mut json)?;
+        let checkpoint : Checkpoint = serde_json::from_str(&json).map_err(|e| ModelError::Format(e.to_string()))?;
+        if checkpoint.version > MODEL_FORMAT_VERSION {
+            return Err(ModelError::UnsupportedVersion(checkpoint.version));
+        }
+        Ok(checkpoint)
+    }
+}
+
+fn has_null(value : &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::Null => true,
+        serde_json::Value::Array(values) => values.iter().any(has_null),
+        serde_json::Value::Object(fields) => fields.values().any(has_null),
+        _ => false,
+    }
+}
+
+/// Writes checkpoints to a directory and rotates them.
+///
+/// Checkpoints are named `checkpoint_<epoch>.json`. Only the latest `keep_last` of them are
+/// kept, and with `keep_best` the one with the lowest metric is also copied to `best.json`.
+///
+/// ```no_run
+/// # use PotatoNeuralNet::CheckpointManager;
+/// let checkpoints = CheckpointManager::new("./checkpoints").unwrap()
+///     .with_interval(5)
+///     .with_keep_last(3)
+///     .with_keep_best(true);
+/// if let Some(checkpoint) = checkpoints.latest().unwrap() {
+///     println!("resuming after epoch {}", checkpoint.epoch);
+/// }
+/// ```
+pub struct CheckpointManager {
+    dir : PathBuf,
+    interval : usize,
+    keep_last : usize,
+    keep_best : bool,
+    best_metric : Cell<Option<f32>>,
+}
+impl CheckpointManager {
+    /// Checkpoints every epoch into `dir`, creating it if needed, keeping the last 5.
+    pub fn new<P : AsRef<Path>>(dir : P) -> Result<CheckpointManager, ModelError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let manager = CheckpointManager { dir, interval: 1, keep_last: 5, keep_best: false, best_metric: Cell::new(None) };
+        if let Some(best) = manager.best()? {
+            manager.best_metric.set(best.metric);
+        }
+        Ok(manager)
+    }
+    /// Only checkpoints every `epochs` epochs.
+    pub fn with_interval(self, epochs : usize) -> CheckpointManager {
+        CheckpointManager { interval: epochs.max(1), ..self }
+    }
+    pub fn with_keep_last(self, keep_last : usize) -> CheckpointManager {
+        CheckpointManager { keep_last: keep_last.max(1), ..self }
+    }
+    pub fn with_keep_best(self, keep_best : bool) -> CheckpointManager {
+        CheckpointManager { keep_best, ..self }
+    }
+
+    /// Whether a checkpoint is due after `epoch` (counting from 0).
+    pub fn should_save(&self, epoch : usize) -> bool {
+        (epoch + 1).is_multiple_of(self.interval)
+    }
+    /// Writes the checkpoint, then applies the rotation policy. Returns the path written.
+    pub fn save(&self, checkpoint : &Checkpoint) -> Result<PathBuf, ModelError> {
+        let path = self.dir.join(format!("checkpoint_{:08}.json", checkpoint.epoch));
+        checkpoint.save(&path)?;
+        if self.keep_best {
+            if let Some(metric) = checkpoint.metric {
+                if self.best_metric.get().is_none_or(|best| metric < best) {
+                    checkpoint.save(self.dir.join("best.json"))?;
+                    self.best_metric.set(Some(metric));
+                }
+            }
+        }
+        let mut saved = self.list()?;
+        while saved.len() > self.keep_last {
+            fs::remove_file(saved.remove(0))?;
+        }
+        Ok(path)
+    }
+    /// The most recent checkpoint, if any.
+    pub fn latest(&self) -> Result<Option<Checkpoint>, ModelError> {
+        match self.list()?.last() {
+            Some(path) => Checkpoint::load(path).map(Some),
+            None => Ok(None),
+        }
+    }
+    /// The checkpoint with the lowest metric, if `keep_best` was used.
+    pub fn best(&self) -> Result<Option<Checkpoint>, ModelError> {
+        let path = self.dir.join("best.json");
+        if path.exists() {
+            Checkpoint::load(path).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+    /// The rotated checkpoints, oldest first.
+    fn list(&self) -> Result<Vec<PathBuf>, ModelError> {
+        let mut paths = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            if name.starts_with("checkpoint_") && name.ends_with(".json") {
+                paths.push(path);
+            }
+        }
+        // the epochs are zero padded, so the names sort chronologically
+        paths.sort();
+        Ok(paths)
+    }
+}
@@ -0,0 +1,245 @@
+use crate::data_importer::{ConsumableType, ReadError};
+use crate::data_set::{ClassifiedData, DatasetLoader, Datum, FileError};
+use std::fs::File;
+use std::io::{BufReader, prelude::*};
+use std::path::PathBuf;
+
+// 32x32 pixels, 3 channels
+const PIXELS : usize = 1024;
+const IMAGE_BYTES : usize = 3 * PIXELS;
+
+/// Which CIFAR binary format to read, and for CIFAR-100 which label to use.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CifarVariant {
+    /// One label byte per record.
+    Cifar10,
+    /// Two label bytes per record, using the first (one of 20 superclasses).
+    Cifar100Coarse,
+    /// Two label bytes per record, using the second (one of 100 classes).
+    Cifar100Fine,
+}
+impl CifarVariant {
+    fn label_bytes(&self) -> usize {
+        match self {
+            CifarVariant::Cifar10 => 1,
+            CifarVariant::Cifar100Coarse | CifarVariant::Cifar100Fine => 2,
+        }
+    }
+    pub fn record_size(&self) -> usize {
+        self.label_bytes() + IMAGE_BYTES
+    }
+}
+
+/// Order of the pixel bytes handed to `Datum::from`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChannelLayout {
+    /// All red values, then all green, then all blue, as stored in the batch files.
+    Planar,
+    /// Red, green and blue of the first pixel, then of the second, and so on.
+    Interleaved,
+    /// One luma byte per pixel.
+    Grayscale,
+}
+
+/// One image of a CIFAR batch file, as stored.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CifarRecord {
+    /// The coarse label of CIFAR-100 records.
+    pub coarse_label : Option<u8>,
+    pub label : u8,
+    /// Channel planar pixels.
+    pub pixels : Vec<u8>,
+}
+impl CifarRecord {
+    /// The pixels rearranged to `layout`.
+    pub fn to_layout(&self, layout : ChannelLayout) -> Vec<u8> {
+        let (r, rest) = self.pixels.split_at(PIXELS);
+        let (g, b) = rest.split_at(PIXELS);
+        match layout {
+            ChannelLayout::Planar => self.pixels.clone(),
+            ChannelLayout::Interleaved => (0..PIXELS).flat_map(|i| [r[i], g[i], b[i]]).collect(),
+            ChannelLayout::Grayscale => (0..PIXELS)
+                .map(|i| (0.299 * r[i] as f32 + 0.587 * g[i] as f32 + 0.114 * b[i] as f32).round() as u8)
+                .collect(),
+        }
+    }
+}
+impl ConsumableType<3073> for CifarRecord {
+    fn from_arr(data_in : [u8; 3073]) -> Self {
+        CifarRecord { coarse_label: None, label: data_in[0], pixels: data_in[1..].to_vec() }
+    }
+
+    fn from_vec(data_in: Vec<u8>) -> Self {
+        CifarRecord { coarse_label: None, label: data_in[0], pixels: data_in[1..3073].to_vec() }
+    }
+}
+impl ConsumableType<3074> for CifarRecord {
+    fn from_arr(data_in : [u8; 3074]) -> Self {
+        CifarRecord { coarse_label: Some(data_in[0]), label: data_in[1], pixels: data_in[2..].to_vec() }
+    }
+
+    fn from_vec(data_in: Vec<u8>) -> Self {
+        CifarRecord { coarse_label: Some(data_in[0]), label: data_in[1], pixels: data_in[2..3074].to_vec() }
+    }
+}
+
+/// Streams the records out of CIFAR-10 or CIFAR-100 binary batch files, one file after the
+/// other, without holding a whole batch in memory.
+///
+/// ```no_run
+/// # use PotatoNeuralNet::{ChannelLayout, CifarLoader, CifarVariant};
+/// let batches = ["./cifar/data_batch_1.bin", "./cifar/data_batch_2.bin"];
+/// let loader = CifarLoader::new(&batches, CifarVariant::Cifar10).unwrap()
+///     .with_layout(ChannelLayout::Grayscale);
+/// ```
+pub struct CifarLoader {
+    files : Vec<PathBuf>,
+    reader : Option<BufReader<File>>,
+    next_file : usize,
+    variant : CifarVariant,
+    layout : ChannelLayout,
+    total : usize,
+    current : usize,
+}
+impl CifarLoader {
+    /// Checks that every batch file is readable and holds whole records.
+    ///
+    /// ```
+    /// # use PotatoNeuralNet::{ChannelLayout, CifarLoader, CifarVariant, ClassifiedData, DatasetLoader, Datum, FileError};
+    /// # use std::{cell::RefCell, rc::Rc};
+    /// // the number of bytes handed over and the first few of them
+    /// #[derive(Copy, Clone)]
+    /// struct Head(usize, [u8; 6]);
+    /// # impl Datum<6> for Head {
+    /// #     type DataType = f32;
+    /// #     type ReceiverType = Rc<RefCell<[f32; 6]>>;
+    /// #     fn from(data: Vec<u8>) -> Option<Self> { Some(Head(data.len(), data.get(..6)?.try_into().ok()?)) }
+    /// #     fn seed(&self, receiver: Self::ReceiverType) { *receiver.borrow_mut() = self.1.map(f32::from); }
+    /// # }
+    /// # fn heads(mut loader: CifarLoader) -> Vec<(f32, usize, [u8; 6])> {
+    /// #     let mut heads = vec![];
+    /// #     while DatasetLoader::<Head, 6>::has_next(&loader) {
+    /// #         let sample : ClassifiedData<Head, 6> = loader.next().unwrap();
+    /// #         heads.push((sample.get_class(), sample.get_data().0, sample.get_data().1));
+    /// #     }
+    /// #     heads
+    /// # }
+    /// let dir = std::env::temp_dir().join(format!("potato_cifar_{}", std::process::id()));
+    /// std::fs::create_dir_all(&dir).unwrap();
+    /// // a red pixel, a blue pixel and the rest gray, as red, green and blue planes
+    /// let mut planes = vec![100u8; 3 * 1024];
+    /// planes[..2].copy_from_slice(&[255, 0]);
+    /// planes[1024..1026].copy_from_slice(&[0, 0]);
+    /// planes[2048..2050].copy_from_slice(&[0, 255]);
+    /// // CIFAR-100 records start with the coarse and the fine label, one record per file
+    /// let mut paths = vec![];
+    /// for (name, coarse, fine) in [("first.bin", 3, 42), ("second.bin", 19, 99)] {
+    ///     let path = dir.join(name);
+    ///     std::fs::write(&path, [&[coarse, fine][..], &planes].concat()).unwrap();
+    ///     paths.push(path.to_str().unwrap().to_string());
+    /// }
+    /// let paths : Vec<&str> = paths.iter().map(String::as_str).collect();
+    ///
+    /// let planar = [255, 0, 100, 100, 100, 100];
+    /// let coarse = CifarLoader::new(&paths, CifarVariant::Cifar100Coarse).unwrap();
+    /// assert_eq!(coarse.len(), 2);
+    /// assert_eq!(heads(coarse), vec![(3.0, 3072, planar), (19.0, 3072, planar)]);
+    /// let fine = CifarLoader::new(&paths, CifarVariant::Cifar100Fine).unwrap();
+    /// assert_eq!(heads(fine), vec![(42.0, 3072, planar), (99.0, 3072, planar)]);
+    ///
+    /// let interleaved = CifarLoader::new(&paths, CifarVariant::Cifar100Fine).unwrap().with_layout(ChannelLayout::Interleaved);
+    /// assert_eq!(heads(interleaved)[0], (42.0, 3072, [255, 0, 0, 0, 0, 255]));
+    /// // luma of red, blue and gray
+    /// let grayscale = CifarLoader::new(&paths, CifarVariant::Cifar100Fine).unwrap().with_layout(ChannelLayout::Grayscale);
+    /// assert_eq!(heads(grayscale)[1], (99.0, 1024, [76, 29, 100, 100, 100, 100]));
+    ///
+    /// // two 3074 byte records aren't whole 3073 byte CIFAR-10 records
+    /// assert!(matches!(CifarLoader::new(&paths, CifarVariant::Cifar10), Err(FileError::IncorrectFormat(..))));
+    /// let missing = dir.join("missing.bin");
+    /// assert!(matches!(CifarLoader::new(&[missing.to_str().unwrap()], CifarVariant::Cifar10), Err(FileError::FileNotReadable(..))));
+    /// std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn new(paths : &[&str], variant : CifarVariant) -> Result<CifarLoader, FileError> {
+        let mut total = 0;
+        for path in paths {
+            let len = std::fs::metadata(path).map_err(|e| FileError::FileNotReadable(path.to_string(), e))?.len() as usize;
+            if !len.is_multiple_of(variant.record_size()) {
+                return Err(FileError::IncorrectFormat(path.to_string(), format!("{variant:?} batch of {} byte records", variant.record_size())));
+            }
+            total += len / variant.record_size();
+        }
+        Ok(CifarLoader {
+            files: paths.iter().map(PathBuf::from).collect(),
+            reader: None,
+            next_file: 0,
+            variant,
+            layout: ChannelLayout::Planar,
+            total,
+            current: 0,
+        })
+    }
+    pub fn with_layout(self, layout : ChannelLayout) -> CifarLoader {
+        CifarLoader { layout, ..self }
+    }
+    /// Number of records in all the batch files.
+    pub fn len(&self) -> usize {
+        self.total
+    }
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    fn read_record(&mut self) -> std::io::Result<CifarRecord> {
+        loop {
+            if let Some(reader) = self.reader.as_mut() {
+                let record = match self.variant {
+                    CifarVariant::Cifar10 => {
+                        let mut buf = [0u8; 3073];
+                        reader.read_exact(&mut buf).map(|_| <CifarRecord as ConsumableType<3073>>::from_arr(buf))
+                    }
+                    _ => {
+                        let mut buf = [0u8; 3074];
+                        reader.read_exact(&mut buf).map(|_| <CifarRecord as ConsumableType<3074>>::from_arr(buf))
+                    }
+                };
+                match record {
+                    Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {}
+                    other => return other,
+                }
+            }
+            // current file exhausted, move on to the next one
+            let Some(path) = self.files.get(self.next_file) else {
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            };
+            self.next_file += 1;
+            self.reader = Some(BufReader::new(File::open(path)?));
+        }
+    }
+    fn current_path(&self) -> String {
+        self.files[self.next_file.saturating_sub(1)].display().to_string()
+    }
+}
+impl <D : Datum<SIZE>, const SIZE : usize> DatasetLoader<D, SIZE> for CifarLoader {
+    fn next(&mut self) -> Result<ClassifiedData<D, SIZE>, FileError> {
+        let index = self.current;
+        self.current += 1;
+        let record = match self.read_record() {
+            Ok(record) => record,
+            Err(e) => {
+                // the stream is broken, later records can't be read either
+                self.current = self.total;
+                return Err(FileError::SampleNotReadable(index, self.current_path(), ReadError::Io(e)));
+            }
+        };
+        let label = match self.variant {
+            CifarVariant::Cifar100Coarse => record.coarse_label.unwrap_or(record.label),
+            _ => record.label,
+        };
+        D::from(record.to_layout(self.layout)).map(|data| ClassifiedData::new(data, label as f32))
+            .ok_or_else(|| FileError::SampleRejected(index, self.current_path()))
+    }
+
+    fn has_next(&self) -> bool {
+        self.current < self.total
+    }
+}
@@ -0,0 +1,275 @@
+use crate::data_set::{ClassifiedData, DatasetLoader, Datum, FileError, Label, RandomAccessLoader};
+
+/// Picks a column of a CSV file, by position or by its header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CsvColumn {
+    Index(usize),
+    /// Requires the file to have a header row.
+    Name(String),
+}
+
+/// What to do with empty cells (or `NA`, `NaN`, `null`, `?`) in the feature columns.
+/// Rows missing a label are always dropped with `DropRow`, and an error otherwise.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MissingValues {
+    /// Fail the whole load.
+    Error,
+    /// Skip the row.
+    DropRow,
+    /// Replace the value with a constant.
+    FillConstant(f32),
+    /// Replace the value with the mean of the values present in its column.
+    FillMean,
+}
+
+/// Configures how a `CsvLoader` reads a table.
+#[derive(Clone, Debug)]
+pub struct CsvOptions {
+    has_header : bool,
+    delimiter : u8,
+    labels : Vec<CsvColumn>,
+    features : Option<Vec<CsvColumn>>,
+    missing : MissingValues,
+}
+impl CsvOptions {
+    /// Comma separated, with a header row, every other column is a feature and missing
+    /// values are an error.
+    pub fn new(label : CsvColumn) -> CsvOptions {
+        CsvOptions { has_header: true, delimiter: b',', labels: vec![label], features: None, missing: MissingValues::Error }
+    }
+    /// Reads the label from several columns, giving each sample a vector label, e.g. for
+    /// one-hot classes or multi-output regression.
+    pub fn with_label_columns(self, labels : Vec<CsvColumn>) -> CsvOptions {
+        CsvOptions { labels, ..self }
+    }
+    pub fn with_header(self, has_header : bool) -> CsvOptions {
+        CsvOptions { has_header, ..self }
+    }
+    pub fn with_delimiter(self, delimiter : u8) -> CsvOptions {
+        CsvOptions { delimiter, ..self }
+    }
+    /// Only uses these columns as features, in this order.
+    pub fn with_features(self, features : Vec<CsvColumn>) -> CsvOptions {
+        CsvOptions { features: Some(features), ..self }
+    }
+    pub fn with_missing_values(self, missing : MissingValues) -> CsvOptions {
+        CsvOptions { missing, ..self }
+    }
+}
+
+/// Loads a table of numbers from a CSV file, one sample per row.
+///
+/// The whole file is read and checked when the loader is created, so problems are reported
+/// up front. The features of each row are passed to `Datum::from_values`.
+///
+/// ```no_run
+/// # use PotatoNeuralNet::{CsvColumn, CsvLoader, CsvOptions, MissingValues};
+/// // iris, with the species numbered 0 to 2 in a `class` column
+/// let loader = CsvLoader::new("./dataset/iris.csv", CsvOptions::new(CsvColumn::Name("class".into()))
+///     .with_missing_values(MissingValues::FillMean)).unwrap();
+/// ```
+pub struct CsvLoader {
+    path : String,
+    rows : Vec<(Vec<f32>, Label)>,
+    current : usize,
+}
+impl CsvLoader {
+    /// Reads and checks the whole file. Missing values are handled as `options` says, and
+    /// rows in the errors count the header too.
+    ///
+    /// ```
+    /// # use PotatoNeuralNet::{ClassifiedData, CsvColumn, CsvLoader, CsvOptions, DatasetLoader, Datum, FileError, Label, MissingValues};
+    /// # use std::{cell::RefCell, rc::Rc};
+    /// # #[derive(Copy, Clone)]
+    /// # struct Pair([f32; 2]);
+    /// # impl Datum<2> for Pair {
+    /// #     type DataType = f32;
+    /// #     type ReceiverType = Rc<RefCell<[f32; 2]>>;
+    /// #     fn from(_: Vec<u8>) -> Option<Self> { None }
+    /// #     fn from_values(values: Vec<f32>) -> Option<Self> { Some(Pair(values.try_into().ok()?)) }
+    /// #     fn seed(&self, receiver: Self::ReceiverType) { *receiver.borrow_mut() = self.0; }
+    /// # }
+    /// # fn rows(mut loader: CsvLoader) -> Vec<([f32; 2], Label)> {
+    /// #     let mut rows = vec![];
+    /// #     while DatasetLoader::<Pair, 2>::has_next(&loader) {
+    /// #         let sample : ClassifiedData<Pair, 2> = loader.next().unwrap();
+    /// #         rows.push((sample.get_data().0, sample.get_label().clone()));
+    /// #     }
+    /// #     rows
+    /// # }
+    /// let dir = std::env::temp_dir().join(format!("potato_csv_{}", std::process::id()));
+    /// std::fs::create_dir_all(&dir).unwrap();
+    /// let path = dir.join("gaps.csv");
+    /// std::fs::write(&path, "a,b,class\n1,2,0\nNA,4,1\n5,,2\n").unwrap();
+    /// let path = path.to_str().unwrap();
+    /// let options = CsvOptions::new(CsvColumn::Name("class".into()));
+    ///
+    /// let error = CsvLoader::new(path, options.clone());
+    /// assert!(matches!(error, Err(FileError::MissingValue(_, 2, column)) if column == "a"));
+    ///
+    /// let loader = CsvLoader::new(path, options.clone().with_missing_values(MissingValues::DropRow)).unwrap();
+    /// assert_eq!(rows(loader), vec![([1.0, 2.0], Label::Scalar(0.0))]);
+    ///
+    /// let loader = CsvLoader::new(path, options.clone().with_missing_values(MissingValues::FillConstant(-1.0))).unwrap();
+    /// assert_eq!(rows(loader), vec![
+    ///     ([1.0, 2.0], Label::Scalar(0.0)),
+    ///     ([-1.0, 4.0], Label::Scalar(1.0)),
+    ///     ([5.0, -1.0], Label::Scalar(2.0)),
+    /// ]);
+    ///
+    /// // the means of the values present, (1 + 5) / 2 and (2 + 4) / 2
+    /// let loader = CsvLoader::new(path, options.clone().with_missing_values(MissingValues::FillMean)).unwrap();
+    /// assert_eq!(rows(loader)[1..], [([3.0, 4.0], Label::Scalar(1.0)), ([5.0, 3.0], Label::Scalar(2.0))]);
+    ///
+    /// // another label column and features in another order, the row missing its label is dropped
+    /// let options = CsvOptions::new(CsvColumn::Name("b".into()))
+    ///     .with_features(vec![CsvColumn::Name("class".into()), CsvColumn::Index(0)])
+    ///     .with_missing_values(MissingValues::FillConstant(0.0));
+    /// assert!(matches!(CsvLoader::new(path, options.clone()), Err(FileError::MissingValue(_, 3, column)) if column == "b"));
+    /// let options = options.with_missing_values(MissingValues::DropRow);
+    /// assert_eq!(rows(CsvLoader::new(path, options).unwrap()), vec![([0.0, 1.0], Label::Scalar(2.0))]);
+    ///
+    /// let missing = CsvLoader::new(path, CsvOptions::new(CsvColumn::Name("species".into())));
+    /// assert!(matches!(missing, Err(FileError::ColumnNotFound(_, column)) if column == "species"));
+    ///
+    /// // without a header the first row is data, and columns can only be picked by position
+    /// let path = dir.join("plain.csv");
+    /// std::fs::write(&path, "1;0.5;2\n0;1.5;x\n").unwrap();
+    /// let path = path.to_str().unwrap();
+    /// let options = CsvOptions::new(CsvColumn::Index(0)).with_header(false).with_delimiter(b';');
+    /// let error = CsvLoader::new(path, options.clone());
+    /// assert!(matches!(error, Err(FileError::InvalidValue(_, 1, column, value)) if column == "2" && value == "x"));
+    /// let options = options.with_features(vec![CsvColumn::Index(1), CsvColumn::Index(1)]);
+    /// assert_eq!(rows(CsvLoader::new(path, options).unwrap()), vec![
+    ///     ([0.5, 0.5], Label::Scalar(1.0)),
+    ///     ([1.5, 1.5], Label::Scalar(0.0)),
+    /// ]);
+    /// let by_name = CsvLoader::new(path, CsvOptions::new(CsvColumn::Name("class".into())).with_header(false));
+    /// assert!(matches!(by_name, Err(FileError::ColumnNotFound(..))));
+    /// std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn new(path : &str, options : CsvOptions) -> Result<CsvLoader, FileError> {
+        let file = std::fs::File::open(path).map_err(|e| FileError::FileNotReadable(path.into(), e))?;
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(options.has_header)
+            .delimiter(options.delimiter)
+            .from_reader(file);
+        let headers : Vec<String> = if options.has_header {
+            let headers = reader.headers().map_err(|e| FileError::InvalidCsv(path.into(), e))?;
+            headers.iter().map(|h| h.trim().to_string()).collect()
+        } else {
+            vec![]
+        };
+
+        let mut records = vec![];
+        for record in reader.records() {
+            let record = record.map_err(|e| FileError::InvalidCsv(path.into(), e))?;
+            records.push(record.iter().map(|x| x.trim().to_string()).collect::<Vec<_>>());
+        }
+        let width = records.first().map(|r| r.len()).unwrap_or(headers.len());
+
+        let labels = options.labels.iter().map(|c| resolve(path, c, &headers, width)).collect::<Result<Vec<_>, _>>()?;
+        let features = match &options.features {
+            Some(columns) => columns.iter().map(|c| resolve(path, c, &headers, width)).collect::<Result<Vec<_>, _>>()?,
+            None => (0..width).filter(|i| !labels.contains(i)).collect(),
+        };
+
+        // parse everything first, the column means need every row
+        let mut parsed : Vec<(Row, Row)> = vec![];
+        // rows in errors count the header too, so they match the lines of the file
+        let first_row = options.has_header as usize;
+        for (row, record) in records.iter().enumerate() {
+            let row = first_row + row;
+            let cell = |column : usize| -> Result<Option<f32>, FileError> {
+                let value = record.get(column).map(String::as_str).unwrap_or("");
+                if is_missing(value) {
+                    return Ok(None);
+                }
+                value.parse().map(Some).map_err(|_| FileError::InvalidValue(path.into(), row, column_name(&headers, column), value.into()))
+            };
+            let label_values = labels.iter().map(|c| cell(*c)).collect::<Result<Vec<_>, _>>()?;
+            let feature_values = features.iter().map(|c| cell(*c)).collect::<Result<Vec<_>, _>>()?;
+            parsed.push((feature_values, label_values));
+        }
+
+        let means : Vec<f32> = (0..features.len()).map(|j| {
+            let present : Vec<f32> = parsed.iter().filter_map(|(f, _)| f[j]).collect();
+            if present.is_empty() { 0.0 } else { present.iter().sum::<f32>() / present.len() as f32 }
+        }).collect();
+
+        let mut rows = vec![];
+        for (row, (feature_values, label_values)) in parsed.into_iter().enumerate() {
+            let row = first_row + row;
+            let missing_column = feature_values.iter().position(|v| v.is_none()).map(|j| features[j]);
+            let label_value = match label_values.iter().position(|v| v.is_none()) {
+                None if labels.len() == 1 => Label::Scalar(label_values[0].unwrap_or_default()),
+                None => Label::Vector(label_values.into_iter().flatten().collect()),
+                Some(_) if options.missing == MissingValues::DropRow => continue,
+                Some(j) => return Err(FileError::MissingValue(path.into(), row, column_name(&headers, labels[j]))),
+            };
+            let values = match (missing_column, options.missing) {
+                (None, _) => feature_values.into_iter().flatten().collect(),
+                (Some(column), MissingValues::Error) => return Err(FileError::MissingValue(path.into(), row, column_name(&headers, column))),
+                (Some(_), MissingValues::DropRow) => continue,
+                (Some(_), MissingValues::FillConstant(fill)) => feature_values.into_iter().map(|v| v.unwrap_or(fill)).collect(),
+                (Some(_), MissingValues::FillMean) => feature_values.into_iter().zip(means.iter()).map(|(v, mean)| v.unwrap_or(*mean)).collect(),
+            };
+            rows.push((values, label_value));
+        }
+        Ok(CsvLoader { path: path.into(), rows, current: 0 })
+    }
+    /// Number of rows that will be loaded.
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+}
+impl <D : Datum<SIZE>, const SIZE : usize> DatasetLoader<D, SIZE> for CsvLoader {
+    fn next(&mut self) -> Result<ClassifiedData<D, SIZE>, FileError> {
+        self.current += 1;
+        self.load(self.current - 1)
+    }
+
+    fn has_next(&self) -> bool {
+        self.current < self.rows.len()
+    }
+}
+impl <D : Datum<SIZE>, const SIZE : usize> RandomAccessLoader<D, SIZE> for CsvLoader {
+    fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    fn get_class(&self, index : usize) -> f32 {
+        self.rows[index].1.class()
+    }
+
+    fn load(&mut self, index : usize) -> Result<ClassifiedData<D, SIZE>, FileError> {
+        let (values, label) = self.rows[index].clone();
+        D::from_values(values).map(|data| ClassifiedData::new(data, label))
+            .ok_or_else(|| FileError::SampleRejected(index, self.path.clone()))
+    }
+}
+
+/// The cells of one row, `None` where a value is missing.
+type Row = Vec<Option<f32>>;
+
+fn resolve(path : &str, column : &CsvColumn, headers : &[String], width : usize) -> Result<usize, FileError> {
+    let index = match column {
+        CsvColumn::Index(i) => Some(*i).filter(|i| *i < width),
+        CsvColumn::Name(name) => headers.iter().position(|h| h == name),
+    };
+    index.ok_or_else(|| FileError::ColumnNotFound(path.into(), match column {
+        CsvColumn::Index(i) => i.to_string(),
+        CsvColumn::Name(name) => name.clone(),
+    }))
+}
+
+fn column_name(headers : &[String], column : usize) -> String {
+    headers.get(column).cloned().unwrap_or_else(|| column.to_string())
+}
+
+fn is_missing(value : &str) -> bool {
+    value.is_empty() || ["na", "n/a", "nan", "null", "?"].contains(&value.to_ascii_lowercase().as_str())
+}
@@ -13,77 +13,435 @@ pub trait DataReader {
 /// Represents a type that can be created from a vector or array with a certain size.
 /// 
 /// ConsumableType represents a type that can be constructed from a stream of bytes. A lot of the 
-/// integral and float types implement this trait. 
+/// integral and float types implement this trait. `from_arr` and `from_vec` read numbers as
+/// big-endian; `from_bytes` lets the caller pick.
 /// 
 /// ```
+/// # use PotatoNeuralNet::{ConsumableType, Endianness};
 /// f32::from_arr([0x0, 0x0, 0x0, 0x0]);
-/// u32::from_vec(vec![0x0, 0x1, 0x2, 0x3]);
+/// u8::from_vec(vec![0x0, 0x1, 0x2, 0x3]);
+/// assert_eq!(u16::from_bytes([0x1, 0x0], Endianness::Little), 1);
 /// ```
 pub trait ConsumableType<const SIZE: usize> {
     fn from_arr(data_in : [u8; SIZE]) -> Self;
     fn from_vec(data_in: Vec<u8>) -> Self;
+    /// Builds the value with the given byte order. Types that aren't a single number ignore it.
+    fn from_bytes(data_in : [u8; SIZE], _endianness : Endianness) -> Self
+    where
+        Self: Sized,
+    {
+        Self::from_arr(data_in)
+    }
+}
+
+/// Byte order of the numbers in a binary file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+    /// Whatever the machine running the code uses, for files written by local tools.
+    Native,
 }
 
 
+/// Why a `DataReader` could not read its input.
+#[derive(Debug)]
 pub enum ReadError {
-    FormatException
+    Io(std::io::Error),
+    /// The PNG signature or header chunks could not be read.
+    InvalidHeader(png::DecodingError),
+    /// The header was fine but the image data could not be decoded.
+    CorruptData(png::DecodingError),
+    /// A colour type the reader cannot convert from.
+    UnsupportedColorType(png::ColorType),
+    /// (width, height) of an image smaller than the requested center crop.
+    ImageTooSmall(u32, u32),
+    /// (width, height) of a target shape with a zero side.
+    InvalidTargetShape(u32, u32),
+}
+impl std::fmt::Display for ReadError {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadError::Io(e) => write!(f, "{e}"),
+            ReadError::InvalidHeader(e) => write!(f, "invalid PNG header: {e}"),
+            ReadError::CorruptData(e) => write!(f, "corrupt PNG image data: {e}"),
+            ReadError::UnsupportedColorType(c) => write!(f, "unsupported PNG colour type {c:?}"),
+            ReadError::ImageTooSmall(w, h) => write!(f, "image of {w}x{h} is smaller than the crop"),
+            ReadError::InvalidTargetShape(w, h) => write!(f, "invalid target shape {w}x{h}"),
+        }
+    }
+}
+impl std::error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReadError::Io(e) => Some(e),
+            ReadError::InvalidHeader(e) | ReadError::CorruptData(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl From<std::io::Error> for ReadError {
+    fn from(e : std::io::Error) -> Self {
+        ReadError::Io(e)
+    }
 }
 
 use std::fs::File;
-use std::io::prelude::*;
+use std::io::{BufReader, SeekFrom, prelude::*};
 /// Represents a packed binary file.
 /// 
 /// Consumes data from a file, then returns the ConsumableType requested.
 /// 
-/// ``` 
-/// let mut reader = BinaryFileReader::new(...);
-/// reader.consume::<f32>::();
-/// let a = reader.consume();
-/// if a > 0.0 {
+/// ```no_run
+/// # use PotatoNeuralNet::{BinaryFileReader, DataReader};
+/// # let mut file = std::fs::File::open("weights.bin").unwrap();
+/// let mut reader = BinaryFileReader::new(&mut file);
+/// let a : Vec<f32> = reader.consume();
+/// if a[0] > 0.0 {
 ///     println!("Wow!");
 /// }
 /// ```
+///
+/// Raw dumps with a header or padding between the values can be read by skipping them:
+///
+/// ```no_run
+/// # use PotatoNeuralNet::{BinaryFileReader, DataReader, Endianness};
+/// # let mut file = std::fs::File::open("dump.bin").unwrap();
+/// // a 16 byte header, then one little-endian f64 every 12 bytes
+/// let mut reader = BinaryFileReader::new(&mut file)
+///     .with_endianness(Endianness::Little)
+///     .with_header(16)
+///     .with_stride(12);
+/// let a : Vec<f64> = reader.consume();
+/// ```
 pub struct BinaryFileReader<'a> {
-    the_file : &'a mut File
+    the_file : &'a mut File,
+    endianness : Endianness,
+    header : u64,
+    stride : Option<usize>,
+    started : bool,
 }
 impl <'a>  DataReader for BinaryFileReader<'a>{
     fn consume<T : ConsumableType<SIZE>, const SIZE: usize>(&mut self) -> Vec<T> {
         let mut ret = vec![];
+        if !self.started {
+            self.started = true;
+            if self.the_file.seek(SeekFrom::Start(self.header)).is_err() {
+                return ret;
+            }
+        }
+        let gap = self.stride.map_or(0, |stride| stride.saturating_sub(SIZE)) as i64;
+        let mut reader = BufReader::new(&mut *self.the_file);
         let mut buf = [0u8; SIZE];
         loop {
-        if let Err(_) = self.the_file.read_exact(&mut buf) {
+        if reader.read_exact(&mut buf).is_err() {
                 break;
         }else {
-                ret.push(T::from_arr(buf));
+                ret.push(T::from_bytes(buf, self.endianness));
+                if gap > 0 && reader.seek_relative(gap).is_err() {
+                    break;
+                }
         }
     }
         ret
     }
 }
 impl <'a> BinaryFileReader<'a> {
-    /// Creates a new BinaryFileReader from the passed file.
-    fn new(f : &'a mut File) -> BinaryFileReader {
-        BinaryFileReader { the_file: f }
+    /// Creates a new BinaryFileReader from the passed file, reading big-endian values from
+    /// the start of the file.
+    pub fn new(f : &'a mut File) -> BinaryFileReader<'a> {
+        BinaryFileReader { the_file: f, endianness: Endianness::Big, header: 0, stride: None, started: false }
+    }
+    pub fn with_endianness(self, endianness : Endianness) -> BinaryFileReader<'a> {
+        BinaryFileReader { endianness, ..self }
+    }
+    /// Skips `bytes` at the start of the file.
+    pub fn with_header(self, bytes : u64) -> BinaryFileReader<'a> {
+        BinaryFileReader { header: bytes, ..self }
+    }
+    /// Reads one value every `bytes`, skipping whatever follows a value up to the next one.
+    /// A stride smaller than the value itself is ignored.
+    pub fn with_stride(self, bytes : usize) -> BinaryFileReader<'a> {
+        BinaryFileReader { stride: Some(bytes), ..self }
     }
 }
+/// The channels of a decoded image, in the order they are stored for each pixel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorLayout {
+    Gray,
+    GrayAlpha,
+    Rgb,
+    Rgba,
+}
+impl ColorLayout {
+    pub fn channels(&self) -> usize {
+        match self {
+            ColorLayout::Gray => 1,
+            ColorLayout::GrayAlpha => 2,
+            ColorLayout::Rgb => 3,
+            ColorLayout::Rgba => 4,
+        }
+    }
+    fn has_alpha(&self) -> bool {
+        matches!(self, ColorLayout::GrayAlpha | ColorLayout::Rgba)
+    }
+    fn is_gray(&self) -> bool {
+        matches!(self, ColorLayout::Gray | ColorLayout::GrayAlpha)
+    }
+}
+
+/// How each channel of a pixel is written to the reader's buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// One byte per channel. 16-bit images keep only their high byte.
+    U8,
+    /// Two big-endian bytes per channel, as stored in the PNG. 8-bit images are scaled up.
+    U16,
+    /// A big-endian `f32` per channel scaled to `0.0..=1.0`, ready for `consume::<f32>()`.
+    F32,
+}
+impl SampleFormat {
+    pub fn bytes(&self) -> usize {
+        match self {
+            SampleFormat::U8 => 1,
+            SampleFormat::U16 => 2,
+            SampleFormat::F32 => 4,
+        }
+    }
+}
+
+/// How the image is brought to a fixed size.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reshape {
+    /// Bilinear resampling to (width, height), ignoring the aspect ratio.
+    Resize(u32, u32),
+    /// Cuts a (width, height) window out of the middle of the image.
+    CenterCrop(u32, u32),
+}
+
+/// Conversions applied by `PNGFileReader` after decoding.
+///
+/// Palettes and low bit depths are always expanded. By default the colour channels of the file
+/// are kept and samples are 8-bit. Colour is turned into gray with the Rec. 601 luma weights,
+/// and alpha is dropped without blending.
+///
+/// ```no_run
+/// # use PotatoNeuralNet::{PNGFileReader, PngOptions, ColorLayout, SampleFormat, DataReader};
+/// # let mut file = std::fs::File::open("digit.png").unwrap();
+/// let options = PngOptions::new()
+///     .with_layout(ColorLayout::Gray)
+///     .with_format(SampleFormat::F32)
+///     .with_resize(32, 32);
+/// let mut reader = PNGFileReader::with_options(&mut file, &options).unwrap();
+/// let pixels : Vec<f32> = reader.consume();
+/// assert_eq!(pixels.len(), 32 * 32);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PngOptions {
+    layout : Option<ColorLayout>,
+    format : SampleFormat,
+    reshape : Option<Reshape>,
+}
+impl PngOptions {
+    pub fn new() -> PngOptions {
+        PngOptions { layout: None, format: SampleFormat::U8, reshape: None }
+    }
+    /// Converts every image to `layout` instead of keeping the channels of the file.
+    pub fn with_layout(self, layout : ColorLayout) -> PngOptions {
+        PngOptions { layout: Some(layout), ..self }
+    }
+    pub fn with_format(self, format : SampleFormat) -> PngOptions {
+        PngOptions { format, ..self }
+    }
+    pub fn with_resize(self, width : u32, height : u32) -> PngOptions {
+        PngOptions { reshape: Some(Reshape::Resize(width, height)), ..self }
+    }
+    pub fn with_center_crop(self, width : u32, height : u32) -> PngOptions {
+        PngOptions { reshape: Some(Reshape::CenterCrop(width, height)), ..self }
+    }
+}
+impl Default for PngOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reads a PNG file and hands out its pixels in a predictable layout, row by row.
 pub struct PNGFileReader {
     buffer : Vec<u8>,
-    current : usize
+    current : usize,
+    width : u32,
+    height : u32,
+    layout : ColorLayout,
+    format : SampleFormat,
 }
 
 impl PNGFileReader {
+    /// Reads the file with the default `PngOptions`: 8-bit samples in the file's own channels.
     pub fn new (f: &mut File) -> Result<PNGFileReader, ReadError> {
-        let decoder = png::Decoder::new(f);
-        let mut reader = decoder.read_info().unwrap();
+        Self::with_options(f, &PngOptions::new())
+    }
+    pub fn with_options(f : &mut File, options : &PngOptions) -> Result<PNGFileReader, ReadError> {
+        let mut decoder = png::Decoder::new(f);
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let mut reader = decoder.read_info().map_err(ReadError::InvalidHeader)?;
         let mut buf = vec![0; reader.output_buffer_size()];
-        if let Err(_) = reader.next_frame(&mut buf) {
-            return Err(ReadError::FormatException)
+        let info = reader.next_frame(&mut buf).map_err(ReadError::CorruptData)?;
+
+        let source = match info.color_type {
+            png::ColorType::Grayscale => ColorLayout::Gray,
+            png::ColorType::GrayscaleAlpha => ColorLayout::GrayAlpha,
+            png::ColorType::Rgb => ColorLayout::Rgb,
+            png::ColorType::Rgba => ColorLayout::Rgba,
+            other => return Err(ReadError::UnsupportedColorType(other)),
+        };
+        let wide = info.bit_depth == png::BitDepth::Sixteen;
+        let mut image = Image::decode(&buf, info.width, info.height, info.line_size, source, wide);
+
+        let layout = options.layout.unwrap_or(source);
+        image = image.convert(layout);
+        match options.reshape {
+            Some(Reshape::Resize(width, height)) => {
+                if width == 0 || height == 0 {
+                    return Err(ReadError::InvalidTargetShape(width, height));
+                }
+                image = image.resize(width, height);
+            }
+            Some(Reshape::CenterCrop(width, height)) => {
+                if width == 0 || height == 0 {
+                    return Err(ReadError::InvalidTargetShape(width, height));
+                }
+                if width > image.width || height > image.height {
+                    return Err(ReadError::ImageTooSmall(image.width, image.height));
+                }
+                image = image.center_crop(width, height);
+            }
+            None => {}
         }
+
         Ok(PNGFileReader {
-            buffer : buf,
-            current: 0
+            buffer : image.encode(options.format),
+            current: 0,
+            width: image.width,
+            height: image.height,
+            layout,
+            format: options.format,
         })
     }
+    /// Width of the image after any resize or crop.
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+    /// Height of the image after any resize or crop.
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+    pub fn get_channels(&self) -> usize {
+        self.layout.channels()
+    }
+    pub fn get_layout(&self) -> ColorLayout {
+        self.layout
+    }
+    pub fn get_format(&self) -> SampleFormat {
+        self.format
+    }
+}
+
+/// Decoded pixels with every sample widened to 16 bits, so conversions only happen once.
+struct Image {
+    width : u32,
+    height : u32,
+    layout : ColorLayout,
+    samples : Vec<u16>,
+}
+impl Image {
+    fn decode(buf : &[u8], width : u32, height : u32, line_size : usize, layout : ColorLayout, wide : bool) -> Image {
+        let row_len = width as usize * layout.channels();
+        let mut samples = Vec::with_capacity(row_len * height as usize);
+        for row in buf.chunks(line_size).take(height as usize) {
+            if wide {
+                samples.extend(row.chunks_exact(2).take(row_len).map(|b| u16::from_be_bytes([b[0], b[1]])));
+            } else {
+                samples.extend(row.iter().take(row_len).map(|&b| b as u16 * 257));
+            }
+        }
+        Image { width, height, layout, samples }
+    }
+
+    fn convert(self, layout : ColorLayout) -> Image {
+        if layout == self.layout {
+            return self;
+        }
+        let channels = self.layout.channels();
+        let mut samples = Vec::with_capacity(self.samples.len() / channels * layout.channels());
+        for pixel in self.samples.chunks_exact(channels) {
+            let alpha = if self.layout.has_alpha() { pixel[channels - 1] } else { u16::MAX };
+            if layout.is_gray() {
+                let gray = if self.layout.is_gray() {
+                    pixel[0]
+                } else {
+                    (0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32).round() as u16
+                };
+                samples.push(gray);
+            } else if self.layout.is_gray() {
+                samples.extend([pixel[0]; 3]);
+            } else {
+                samples.extend(&pixel[..3]);
+            }
+            if layout.has_alpha() {
+                samples.push(alpha);
+            }
+        }
+        Image { layout, samples, ..self }
+    }
+
+    fn resize(self, width : u32, height : u32) -> Image {
+        let channels = self.layout.channels();
+        let sample = |x : usize, y : usize, c : usize| self.samples[(y * self.width as usize + x) * channels + c] as f32;
+        // maps the centre of a target pixel back onto the source grid
+        let source = |i : u32, target : u32, size : u32| {
+            let pos = ((i as f32 + 0.5) * size as f32 / target as f32 - 0.5).clamp(0.0, (size - 1) as f32);
+            let low = pos.floor() as usize;
+            (low, (low + 1).min(size as usize - 1), pos - low as f32)
+        };
+        let mut samples = Vec::with_capacity(width as usize * height as usize * channels);
+        for y in 0..height {
+            let (y0, y1, fy) = source(y, height, self.height);
+            for x in 0..width {
+                let (x0, x1, fx) = source(x, width, self.width);
+                for c in 0..channels {
+                    let top = sample(x0, y0, c) * (1.0 - fx) + sample(x1, y0, c) * fx;
+                    let bottom = sample(x0, y1, c) * (1.0 - fx) + sample(x1, y1, c) * fx;
+                    samples.push((top * (1.0 - fy) + bottom * fy).round() as u16);
+                }
+            }
+        }
+        Image { width, height, samples, ..self }
+    }
+
+    fn center_crop(self, width : u32, height : u32) -> Image {
+        let channels = self.layout.channels();
+        let left = ((self.width - width) / 2) as usize;
+        let top = ((self.height - height) / 2) as usize;
+        let mut samples = Vec::with_capacity(width as usize * height as usize * channels);
+        for y in top..top + height as usize {
+            let start = (y * self.width as usize + left) * channels;
+            samples.extend(&self.samples[start..start + width as usize * channels]);
+        }
+        Image { width, height, samples, ..self }
+    }
+
+    fn encode(&self, format : SampleFormat) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.samples.len() * format.bytes());
+        for &sample in &self.samples {
+            match format {
+                SampleFormat::U8 => out.push((sample >> 8) as u8),
+                SampleFormat::U16 => out.extend(sample.to_be_bytes()),
+                SampleFormat::F32 => out.extend((sample as f32 / u16::MAX as f32).to_be_bytes()),
+            }
+        }
+        out
+    }
 }
 impl DataReader for PNGFileReader {
     fn consume<T : ConsumableType<SIZE>, const SIZE: usize>(&mut self) -> Vec<T> {
@@ -91,9 +449,7 @@ impl DataReader for PNGFileReader {
         let mut buf = [0u8; SIZE];
         loop {
             if self.buffer.len() - self.current >= SIZE {
-            for i in 0..SIZE {
-                buf[i] = self.buffer[i + self.current]
-            }
+            buf.copy_from_slice(&self.buffer[self.current..self.current + SIZE]);
             self.current += SIZE;
                 ret.push(T::from_arr(buf))
         }else {
@@ -104,26 +460,94 @@ impl DataReader for PNGFileReader {
     }
 }
 
-impl ConsumableType<4> for f32 {
-    fn from_arr(data_in : [u8; 4]) -> Self {
-        f32::from_be_bytes(data_in)
-    }
+macro_rules! consumable_number {
+    ($($t:ty => $size:literal),*) => {$(
+        impl ConsumableType<$size> for $t {
+            fn from_arr(data_in : [u8; $size]) -> Self {
+                <$t>::from_be_bytes(data_in)
+            }
 
-    fn from_vec(data_in: Vec<u8>) -> Self {
-        let mut arr = [0;4];
-        arr[0] = data_in[0];
-        arr[1] = data_in[1];
-        arr[2] = data_in[2];
-        arr[3] = data_in[4];
-        Self::from_arr(arr)
+            fn from_vec(data_in: Vec<u8>) -> Self {
+                let mut arr = [0; $size];
+                arr.copy_from_slice(&data_in[..$size]);
+                Self::from_arr(arr)
+            }
+
+            fn from_bytes(data_in : [u8; $size], endianness : Endianness) -> Self {
+                match endianness {
+                    Endianness::Little => <$t>::from_le_bytes(data_in),
+                    Endianness::Big => <$t>::from_be_bytes(data_in),
+                    Endianness::Native => <$t>::from_ne_bytes(data_in),
+                }
+            }
+        }
+    )*};
+}
+consumable_number!(u8 => 1, i8 => 1, u16 => 2, i16 => 2, u32 => 4, i32 => 4, f32 => 4, f64 => 8);
+
+/// An IEEE 754 half-precision float, kept as its bits.
+///
+/// ```
+/// # use PotatoNeuralNet::F16;
+/// assert_eq!(F16(0x3c00).to_f32(), 1.0);
+/// assert_eq!(F16(0xc000).to_f32(), -2.0);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct F16(pub u16);
+impl F16 {
+    pub fn to_f32(self) -> f32 {
+        let exponent = (self.0 >> 10) & 0x1f;
+        let mantissa = (self.0 & 0x3ff) as u32;
+        let magnitude = match exponent {
+            0 => mantissa as f32 * 2f32.powi(-24),
+            0x1f if mantissa == 0 => f32::INFINITY,
+            0x1f => f32::NAN,
+            // rebias the exponent from 15 to 127 and widen the mantissa from 10 to 23 bits
+            _ => f32::from_bits(((exponent as u32 + 112) << 23) | (mantissa << 13)),
+        };
+        if self.0 & 0x8000 == 0 { magnitude } else { -magnitude }
     }
 }
-impl ConsumableType<1> for u8 {
-    fn from_arr(data_in : [u8; 1]) -> Self {
-        data_in[0]
+impl From<F16> for f32 {
+    fn from(value : F16) -> f32 {
+        value.to_f32()
     }
+}
 
-    fn from_vec(data_in: Vec<u8>) -> Self {
-        data_in[0]
+/// A bfloat16, the upper half of an `f32`, kept as its bits.
+///
+/// ```
+/// # use PotatoNeuralNet::Bf16;
+/// assert_eq!(Bf16(0x3f80).to_f32(), 1.0);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Bf16(pub u16);
+impl Bf16 {
+    pub fn to_f32(self) -> f32 {
+        f32::from_bits((self.0 as u32) << 16)
+    }
+}
+impl From<Bf16> for f32 {
+    fn from(value : Bf16) -> f32 {
+        value.to_f32()
     }
-}
\ No newline at end of file
+}
+
+macro_rules! consumable_half {
+    ($($t:ident),*) => {$(
+        impl ConsumableType<2> for $t {
+            fn from_arr(data_in : [u8; 2]) -> Self {
+                $t(u16::from_arr(data_in))
+            }
+
+            fn from_vec(data_in: Vec<u8>) -> Self {
+                $t(u16::from_vec(data_in))
+            }
+
+            fn from_bytes(data_in : [u8; 2], endianness : Endianness) -> Self {
+                $t(u16::from_bytes(data_in, endianness))
+            }
+        }
+    )*};
+}
+consumable_half!(F16, Bf16);
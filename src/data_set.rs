@@ -1,4 +1,6 @@
-use std::cell::RefCell;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 //trait Dataset {
 //    fn get_size(&self);
 //
@@ -11,94 +13,697 @@ use std::marker::PhantomData;
 use std::ops::AddAssign;
 use std::rc::Rc;
 use rand::prelude::*;
+use rand_chacha::ChaCha12Rng;
+use crate::training::class_index;
+
+/// How `Dataset` divides the samples between the training and validation splits.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SplitStrategy {
+    /// Every sample independently goes to validation with the probability of the share,
+    /// so the split only approximately matches it.
+    Random,
+    /// Exactly `round(share * samples)` samples go to validation.
+    Exact,
+    /// Like `Exact`, but within every classification, so each class keeps its proportion
+    /// in both splits.
+    Stratified,
+}
+
+/// Configures how a `Dataset` is split and shuffled.
+///
+/// ```
+/// # use PotatoNeuralNet::{SplitOptions, SplitStrategy};
+/// let options = SplitOptions::new(0.2)
+///     .with_strategy(SplitStrategy::Stratified)
+///     .with_seed(7)
+///     .with_shuffle_on_reset(true);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SplitOptions {
+    validation_share: f32,
+    strategy: SplitStrategy,
+    seed: Option<u64>,
+    shuffle_on_reset: bool,
+}
+impl SplitOptions {
+    /// A random split sending about `validation_share` of the samples to validation, using
+    /// an unseeded generator.
+    pub fn new(validation_share: f32) -> SplitOptions {
+        SplitOptions { validation_share, strategy: SplitStrategy::Random, seed: None, shuffle_on_reset: false }
+    }
+    pub fn with_strategy(self, strategy: SplitStrategy) -> SplitOptions {
+        SplitOptions { strategy, ..self }
+    }
+    /// Seeds the generator used for the split and every later shuffle, which makes them
+    /// reproducible.
+    pub fn with_seed(self, seed: u64) -> SplitOptions {
+        SplitOptions { seed: Some(seed), ..self }
+    }
+    /// Reshuffles the training order on every `Dataset::reset`.
+    pub fn with_shuffle_on_reset(self, shuffle_on_reset: bool) -> SplitOptions {
+        SplitOptions { shuffle_on_reset, ..self }
+    }
+}
+
+/// One of the two halves of a `Dataset`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Split {
+    Training,
+    Validation,
+}
+
+/// Loads one sample of a lazy `Dataset` by its index.
+type Fetch<D, const SIZE: usize> = Box<dyn FnMut(usize) -> Result<ClassifiedData<D, SIZE>, FileError>>;
+
+/// Where the samples of a `Dataset` live.
+enum Storage<D: Datum<SIZE>, const SIZE: usize> {
+    /// Every sample in memory.
+    Eager(Vec<ClassifiedData<D, SIZE>>),
+    /// Only the classifications in memory, samples are read through the loader when needed.
+    Lazy {
+        classes: Vec<f32>,
+        fetch: RefCell<Fetch<D, SIZE>>,
+        cache: RefCell<LruCache<ClassifiedData<D, SIZE>>>,
+        failed: RefCell<BTreeSet<usize>>,
+    },
+}
 
-#[allow(dead_code)]
 pub struct Dataset<D: Datum<SIZE>, L, const SIZE: usize>
 where
     L: DatasetLoader<D, SIZE>,
 {
-    data: Vec<ClassifiedData<D, SIZE>>,
-    validation: Vec<usize>,
-    training: Vec<usize>,
+    storage: Storage<D, SIZE>,
+    validation: RefCell<Vec<usize>>,
+    training: RefCell<Vec<usize>>,
     cur_val: Rc<RefCell<usize>>,
     cur_training: Rc<RefCell<usize>>,
+    // ChaCha rather than StdRng, since its position can be saved in checkpoints
+    rng: RefCell<ChaCha12Rng>,
+    shuffle_on_reset: bool,
+    report: RefCell<LoadReport>,
+    // width of the vector labels, once known
+    label_width: Cell<Option<usize>>,
+    // outputs of the model of the last `check_labels`, which unread lazy labels must fit
+    output_width: Cell<Option<usize>>,
     ld : PhantomData<L>
 }
+
+/// The samples a `Dataset` skipped while loading, with the reason for each.
+#[derive(Clone, Debug, Default)]
+pub struct LoadReport {
+    loaded: usize,
+    // shared, since the errors can't be cloned but the report is handed out by value
+    skipped: Vec<Rc<FileError>>,
+}
+impl LoadReport {
+    /// Number of samples read from the loader. A lazy dataset counts every read, including
+    /// samples read again after they left the cache.
+    pub fn get_loaded(&self) -> usize {
+        self.loaded
+    }
+    pub fn get_skipped(&self) -> &[Rc<FileError>] {
+        &self.skipped
+    }
+    /// Whether every sample was loaded.
+    pub fn is_complete(&self) -> bool {
+        self.skipped.is_empty()
+    }
+}
+impl std::fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "loaded {} samples, skipped {}", self.loaded, self.skipped.len())?;
+        for error in &self.skipped {
+            write!(f, "\n  {error}")?;
+        }
+        Ok(())
+    }
+}
 impl<'a, D, L, const SIZE: usize> Dataset<D, L, SIZE>
 where
     D: Datum<SIZE>,
     L: DatasetLoader<D, SIZE>,
 {
-    pub fn new(mut loader: L, share: f32) -> Dataset<D, L, SIZE> {
+    /// Loads every sample and sends about `share` of them to the validation split at random.
+    ///
+    /// Samples the loader fails on, and samples with a vector label of another width than
+    /// the labels before them, are skipped and listed in `get_load_report`.
+    pub fn new(loader: L, share: f32) -> Dataset<D, L, SIZE> {
+        Self::with_options(loader, SplitOptions::new(share))
+    }
+    /// Like `new`, but the first sample the loader fails on fails the whole load.
+    pub fn try_new(loader: L, share: f32) -> Result<Dataset<D, L, SIZE>, FileError> {
+        Self::try_with_options(loader, SplitOptions::new(share))
+    }
+    /// Samples the loader fails on are skipped and listed in `get_load_report`.
+    pub fn with_options(loader: L, options: SplitOptions) -> Dataset<D, L, SIZE> {
+        match Self::load(loader, options, false) {
+            Ok(value) => value,
+            Err(_) => unreachable!("a lenient load never fails"),
+        }
+    }
+    /// Like `with_options`, but the first sample the loader fails on fails the whole load.
+    ///
+    /// ```no_run
+    /// # use PotatoNeuralNet::{Dataset, Datum, FileSystemLoader, SplitOptions};
+    /// # fn load<D: Datum<1024>>() -> Result<(), Box<dyn std::error::Error>> {
+    /// let loader = FileSystemLoader::new("./dataset/dataset.json")?;
+    /// let data = Dataset::<D, _, 1024>::try_with_options(loader, SplitOptions::new(0.2))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn try_with_options(loader: L, options: SplitOptions) -> Result<Dataset<D, L, SIZE>, FileError> {
+        Self::load(loader, options, true)
+    }
+    fn load(mut loader: L, options: SplitOptions, strict: bool) -> Result<Dataset<D, L, SIZE>, FileError> {
         let mut data = vec![];
-        let mut val = vec![];
-        let mut train = vec![];
-        let mut rand = thread_rng();
-        let mut i = 0;
+        let mut report = LoadReport::default();
+        let width = Cell::new(None);
+        let mut index = 0;
         while loader.has_next() {
-            let a = loader.next();
-            if let Some(x) = a {
-                data.push(x);
-
-                if rand.gen_range(0.0..1.0) < share {
-                    val.push(i);
-                } else {
-                    train.push(i);
-                }
-                i += 1;
+            let sample = loader.next().and_then(|x| check_width(index, x.get_label(), &width, None).map(|_| x));
+            index += 1;
+            match sample {
+                Ok(x) => data.push(x),
+                Err(e) if strict => return Err(e),
+                Err(e) => report.skipped.push(Rc::new(e)),
             }
         }
+        report.loaded = data.len();
+        let classes: Vec<f32> = data.iter().map(|x| x.get_class()).collect();
+        Ok(Self::build(Storage::Eager(data), &classes, options, report, width.get()))
+    }
+    /// Keeps only the classifications in memory and reads samples through the loader when
+    /// they are used, keeping the last `cache_size` of them around.
+    ///
+    /// Meant for datasets that don't fit in memory, e.g. with a `MmapLoader`. Samples the
+    /// loader fails on, or whose vector label has another width than the labels read before
+    /// it, are skipped from then on and listed in `get_load_report`.
+    ///
+    /// ```no_run
+    /// # use PotatoNeuralNet::{Dataset, Datum, MmapLoader, PackedLayout, SplitOptions};
+    /// # fn load<D: Datum<3072> + 'static>() {
+    /// let loader = MmapLoader::new("./cifar/data_batch_1.bin", PackedLayout::new(3073)).unwrap();
+    /// let data = Dataset::<D, _, 3072>::lazy(loader, SplitOptions::new(0.1), 4096);
+    /// # }
+    /// ```
+    pub fn lazy(loader: L, options: SplitOptions, cache_size: usize) -> Dataset<D, L, SIZE>
+    where
+        L: RandomAccessLoader<D, SIZE> + 'static,
+        D: 'static,
+    {
+        let classes: Vec<f32> = (0..loader.len()).map(|i| loader.get_class(i)).collect();
+        let mut loader = loader;
+        let storage = Storage::Lazy {
+            classes: classes.clone(),
+            fetch: RefCell::new(Box::new(move |index| loader.load(index))),
+            cache: RefCell::new(LruCache::new(cache_size)),
+            failed: RefCell::new(BTreeSet::new()),
+        };
+        Self::build(storage, &classes, options, LoadReport::default(), None)
+    }
+    fn build(storage: Storage<D, SIZE>, classes: &[f32], options: SplitOptions, report: LoadReport, label_width: Option<usize>) -> Dataset<D, L, SIZE> {
+        let mut rand = match options.seed {
+            Some(seed) => ChaCha12Rng::seed_from_u64(seed),
+            None => ChaCha12Rng::from_rng(thread_rng()).unwrap(),
+        };
+        let (val, train) = split(classes, &options, &mut rand);
         Dataset {
-            data,
-            validation: val,
-            training: train,
+            storage,
+            validation: RefCell::new(val),
+            training: RefCell::new(train),
             cur_val: Rc::new(RefCell::new(0)),
             cur_training: Rc::new(RefCell::new(0)),
+            rng: RefCell::new(rand),
+            shuffle_on_reset: options.shuffle_on_reset,
+            report: RefCell::new(report),
+            label_width: Cell::new(label_width),
+            output_width: Cell::new(None),
             ld : PhantomData
         }
     }
-    #[allow(dead_code)]
-    pub fn get_validation(&'a self) -> Option<&'a ClassifiedData<D, SIZE>> {
-        let a = match self.validation.get(*self.cur_val.borrow()) {
-            Some(value ) => Some(&self.data[*value]),
-            None => None,
+    /// Fails if the labels can't be targets for a model with `width` outputs, because the
+    /// vector labels have another width or a class isn't one of the outputs. Training and
+    /// evaluation call this before the first sample. A lazy dataset checks the vector labels it hasn't read yet as it reads them,
+    /// and skips those that don't fit.
+    ///
+    /// ```
+    /// # use PotatoNeuralNet::{ClassifiedData, Dataset, DatasetLoader, Datum, FileError, Label};
+    /// # use std::{cell::RefCell, rc::Rc};
+    /// # #[derive(Copy, Clone)]
+    /// # struct Value(f32);
+    /// # impl Datum<1> for Value {
+    /// #     type DataType = f32;
+    /// #     type ReceiverType = Rc<RefCell<[f32; 1]>>;
+    /// #     fn from(data: Vec<u8>) -> Option<Self> { Some(Value(*data.first()? as f32)) }
+    /// #     fn seed(&self, receiver: Self::ReceiverType) { receiver.borrow_mut()[0] = self.0; }
+    /// # }
+    /// // labels as read from a manifest, the third one with a value missing
+    /// struct Manifest(Vec<Label>);
+    /// impl DatasetLoader<Value, 1> for Manifest {
+    ///     fn next(&mut self) -> Result<ClassifiedData<Value, 1>, FileError> {
+    ///         Ok(ClassifiedData::new(Value(0.0), self.0.remove(0)))
+    ///     }
+    ///     fn has_next(&self) -> bool { !self.0.is_empty() }
+    /// }
+    /// let labels = vec![Label::one_hot(0, 3), Label::one_hot(2, 3), Label::Vector(vec![0.0, 1.0])];
+    /// assert!(matches!(Dataset::<Value, _, 1>::try_new(Manifest(labels.clone()), 0.0), Err(FileError::LabelWidthMismatch(2, 2, 3))));
+    ///
+    /// let data = Dataset::<Value, _, 1>::new(Manifest(labels), 0.0);
+    /// assert_eq!(data.len(), 2);
+    /// assert!(data.check_labels(3).is_ok());
+    /// // e.g. a model with 4 outputs
+    /// assert!(matches!(data.check_labels(4), Err(FileError::OutputWidthMismatch(3, 4))));
+    ///
+    /// let data = Dataset::<Value, _, 1>::new(Manifest(vec![Label::Scalar(1.0), Label::Scalar(3.0)]), 0.0);
+    /// assert!(matches!(data.check_labels(3), Err(FileError::InvalidClass(1, _, 3))));
+    /// assert!(data.check_labels(4).is_ok());
+    /// ```
+    pub fn check_labels(&self, width: usize) -> Result<(), FileError> {
+        self.output_width.set(Some(width));
+        if let Some(labels) = self.label_width.get().filter(|labels| *labels != width) {
+            return Err(FileError::OutputWidthMismatch(labels, width));
+        }
+        if width == 1 {
+            // a single output is trained towards the label itself
+            return Ok(());
+        }
+        let invalid = match &self.storage {
+            Storage::Eager(data) => data.iter().enumerate().find_map(|(i, x)| match x.get_label() {
+                Label::Scalar(class) if class_index(*class, width).is_none() => Some((i, *class)),
+                _ => None,
+            }),
+            Storage::Lazy { classes, .. } => classes.iter().enumerate()
+                .find(|(_, class)| class_index(**class, width).is_none())
+                .map(|(i, class)| (i, *class)),
         };
-        self.cur_val.borrow_mut().add_assign(1);
-        a
+        match invalid {
+            Some((index, class)) => Err(FileError::InvalidClass(index, class, width)),
+            None => Ok(()),
+        }
     }
-    pub fn get_training(&'a self) -> Option<&'a ClassifiedData<D, SIZE>> {
-        let a = match self.training.get(*self.cur_training.borrow()) {
-            Some(value) => Some(&self.data[*value]),
-            None => None,
-        };
-        self.cur_training.borrow_mut().add_assign(1);
-        a
+    /// What happened to the samples while loading, so far for a lazy dataset.
+    pub fn get_load_report(&self) -> LoadReport {
+        self.report.borrow().clone()
+    }
+    /// Number of samples in both splits.
+    pub fn len(&self) -> usize {
+        match &self.storage {
+            Storage::Eager(data) => data.len(),
+            Storage::Lazy { classes, .. } => classes.len(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn is_lazy(&self) -> bool {
+        matches!(self.storage, Storage::Lazy { .. })
+    }
+    /// Sample `index`, or `None` if a lazy dataset could not load it. Borrowed from an eager
+    /// dataset, a copy out of the cache of a lazy one.
+    pub fn get_sample(&self, index: usize) -> Option<Cow<'_, ClassifiedData<D, SIZE>>> {
+        match &self.storage {
+            Storage::Eager(data) => data.get(index).map(Cow::Borrowed),
+            Storage::Lazy { fetch, cache, failed, .. } => {
+                if let Some(sample) = cache.borrow_mut().get(index) {
+                    return Some(Cow::Owned(sample.clone()));
+                }
+                if failed.borrow().contains(&index) {
+                    return None;
+                }
+                let mut report = self.report.borrow_mut();
+                let sample = (fetch.borrow_mut())(index)
+                    .and_then(|x| check_width(index, x.get_label(), &self.label_width, self.output_width.get()).map(|_| x));
+                match sample {
+                    Ok(sample) => {
+                        report.loaded += 1;
+                        cache.borrow_mut().insert(index, sample.clone());
+                        Some(Cow::Owned(sample))
+                    }
+                    Err(e) => {
+                        failed.borrow_mut().insert(index);
+                        report.skipped.push(Rc::new(e));
+                        None
+                    }
+                }
+            }
+        }
+    }
+    pub fn get_validation(&self) -> Option<Cow<'_, ClassifiedData<D, SIZE>>> {
+        loop {
+            let index = *self.validation.borrow().get(*self.cur_val.borrow())?;
+            self.cur_val.borrow_mut().add_assign(1);
+            if let Some(sample) = self.get_sample(index) {
+                return Some(sample);
+            }
+        }
+    }
+    pub fn get_training(&self) -> Option<Cow<'_, ClassifiedData<D, SIZE>>> {
+        loop {
+            let index = *self.training.borrow().get(*self.cur_training.borrow())?;
+            self.cur_training.borrow_mut().add_assign(1);
+            if let Some(sample) = self.get_sample(index) {
+                return Some(sample);
+            }
+        }
+    }
+    /// Splits the training samples into mini-batches of `batch_size`, for one epoch.
+    ///
+    /// With `shuffle` the samples are put in a new random order on every call, so calling
+    /// this once per epoch reshuffles every epoch. With `drop_last` the final batch is
+    /// skipped when there aren't enough samples left to fill it.
+    /// This does not touch the `get_training` cursor. A lazy dataset reads each batch as it
+    /// is reached, leaving out samples it fails to load.
+    pub fn get_training_batches(&'a self, batch_size: usize, drop_last: bool, shuffle: bool) -> Batches<'a, D, L, SIZE> {
+        let mut order = self.training.borrow().clone();
+        if shuffle {
+            order.shuffle(&mut *self.rng.borrow_mut());
+        }
+        Batches {
+            data: self,
+            order,
+            batch_size: batch_size.max(1),
+            drop_last,
+            current: 0,
+        }
     }
     pub fn has_training(&self) -> bool {
-        return *self.cur_training.borrow() < self.training.len();
+        *self.cur_training.borrow() < self.training.borrow().len()
     }
+    /// Rewinds both cursors, reshuffling the training order if the dataset was created
+    /// with `SplitOptions::with_shuffle_on_reset`.
     pub fn reset(&self) {
         *self.cur_training.borrow_mut() = 0;
         *self.cur_val.borrow_mut() = 0;
+        if self.shuffle_on_reset {
+            self.training.borrow_mut().shuffle(&mut *self.rng.borrow_mut());
+        }
+    }
+    /// Indices of the training samples, in the order they are visited.
+    pub fn get_training_indices(&self) -> Vec<usize> {
+        self.training.borrow().clone()
+    }
+    pub fn get_validation_indices(&self) -> Vec<usize> {
+        self.validation.borrow().clone()
+    }
+    /// The split, training order, cursors and random generator position, for checkpoints.
+    pub fn get_state(&self) -> DatasetState {
+        let rng = self.rng.borrow();
+        DatasetState {
+            samples: self.len(),
+            training: self.training.borrow().clone(),
+            validation: self.validation.borrow().clone(),
+            cur_training: *self.cur_training.borrow(),
+            cur_val: *self.cur_val.borrow(),
+            rng_seed: rng.get_seed(),
+            rng_stream: rng.get_stream(),
+            rng_word_pos: rng.get_word_pos(),
+        }
+    }
+    /// Restores a state from `get_state` of a dataset loaded from the same samples, including
+    /// its split, so a dataset built with another seed resumes with the saved split.
+    /// Returns false, leaving the dataset untouched, when the state doesn't fit it.
+    pub fn restore_state(&self, state: &DatasetState) -> bool {
+        let mut seen = vec![false; self.len()];
+        let partition = state.training.iter().chain(state.validation.iter())
+            .all(|i| *i < self.len() && !std::mem::replace(&mut seen[*i], true));
+        if state.samples != self.len() || !partition {
+            return false;
+        }
+        *self.training.borrow_mut() = state.training.clone();
+        *self.validation.borrow_mut() = state.validation.clone();
+        *self.cur_training.borrow_mut() = state.cur_training;
+        *self.cur_val.borrow_mut() = state.cur_val;
+        let mut rng = ChaCha12Rng::from_seed(state.rng_seed);
+        rng.set_stream(state.rng_stream);
+        rng.set_word_pos(state.rng_word_pos);
+        *self.rng.borrow_mut() = rng;
+        true
+    }
+    /// Every sample of `split`, without touching the cursors. A lazy dataset reads them one
+    /// at a time as the iterator advances.
+    pub fn get_samples(&'a self, split: Split) -> impl Iterator<Item = Cow<'a, ClassifiedData<D, SIZE>>> + 'a {
+        let indices = match split {
+            Split::Training => self.training.borrow().clone(),
+            Split::Validation => self.validation.borrow().clone(),
+        };
+        indices.into_iter().filter_map(|i| self.get_sample(i))
+    }
+}
+
+/// Fails if `label` is a vector of another width than `width`, or than `outputs` when `width`
+/// isn't known yet. The first vector sets `width`.
+fn check_width(index: usize, label: &Label, width: &Cell<Option<usize>>, outputs: Option<usize>) -> Result<(), FileError> {
+    if let Label::Vector(target) = label {
+        let expected = width.get().or(outputs).unwrap_or(target.len());
+        if target.len() != expected {
+            return Err(FileError::LabelWidthMismatch(index, target.len(), expected));
+        }
+        width.set(Some(expected));
+    }
+    Ok(())
+}
+
+/// Snapshot of the mutable parts of a `Dataset`, see `Dataset::get_state`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DatasetState {
+    samples: usize,
+    training: Vec<usize>,
+    validation: Vec<usize>,
+    cur_training: usize,
+    cur_val: usize,
+    rng_seed: [u8; 32],
+    rng_stream: u64,
+    rng_word_pos: u128,
+}
+
+/// Divides the sample indices into (validation, training), given the class of every sample.
+fn split(classes: &[f32], options: &SplitOptions, rand: &mut ChaCha12Rng) -> (Vec<usize>, Vec<usize>) {
+    let share = options.validation_share;
+    let mut val = vec![];
+    let mut train = vec![];
+    match options.strategy {
+        SplitStrategy::Random => {
+            for i in 0..classes.len() {
+                if rand.gen_range(0.0..1.0) < share {
+                    val.push(i);
+                } else {
+                    train.push(i);
+                }
+            }
+        }
+        SplitStrategy::Exact => {
+            let mut indices: Vec<usize> = (0..classes.len()).collect();
+            indices.shuffle(rand);
+            let n_val = exact_count(indices.len(), share);
+            val.extend_from_slice(&indices[..n_val]);
+            train.extend_from_slice(&indices[n_val..]);
+        }
+        SplitStrategy::Stratified => {
+            // keyed by the bits of the classification so the classes come in a fixed order
+            let mut by_class: BTreeMap<u32, Vec<usize>> = BTreeMap::new();
+            for (i, class) in classes.iter().enumerate() {
+                by_class.entry(class.to_bits()).or_default().push(i);
+            }
+            for (_, mut indices) in by_class {
+                indices.shuffle(rand);
+                let n_val = exact_count(indices.len(), share);
+                val.extend_from_slice(&indices[..n_val]);
+                train.extend_from_slice(&indices[n_val..]);
+            }
+        }
+    }
+    val.sort_unstable();
+    train.sort_unstable();
+    (val, train)
+}
+
+fn exact_count(len: usize, share: f32) -> usize {
+    ((len as f32 * share.clamp(0.0, 1.0)).round() as usize).min(len)
+}
+
+/// Least recently used cache of samples by index.
+struct LruCache<V> {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<usize, (V, u64)>,
+    // last use -> index, the first entry is the one to evict
+    order: BTreeMap<u64, usize>,
+}
+impl<V> LruCache<V> {
+    fn new(capacity: usize) -> LruCache<V> {
+        LruCache { capacity, tick: 0, entries: HashMap::new(), order: BTreeMap::new() }
+    }
+    fn get(&mut self, index: usize) -> Option<&V> {
+        let (value, used) = self.entries.get_mut(&index)?;
+        self.order.remove(used);
+        self.tick += 1;
+        *used = self.tick;
+        self.order.insert(self.tick, index);
+        Some(value)
+    }
+    fn insert(&mut self, index: usize, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        if let Some((_, used)) = self.entries.remove(&index) {
+            self.order.remove(&used);
+        }
+        if self.entries.len() >= self.capacity {
+            if let Some((_, oldest)) = self.order.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.tick += 1;
+        self.entries.insert(index, (value, self.tick));
+        self.order.insert(self.tick, index);
+    }
+}
+
+/// Iterator over the mini-batches of one epoch, see `Dataset::get_training_batches`.
+pub struct Batches<'a, D: Datum<SIZE>, L: DatasetLoader<D, SIZE>, const SIZE: usize> {
+    data: &'a Dataset<D, L, SIZE>,
+    order: Vec<usize>,
+    batch_size: usize,
+    drop_last: bool,
+    current: usize,
+}
+impl<'a, D: Datum<SIZE>, L: DatasetLoader<D, SIZE>, const SIZE: usize> Iterator for Batches<'a, D, L, SIZE> {
+    type Item = Vec<Cow<'a, ClassifiedData<D, SIZE>>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let remaining = self.order.len() - self.current;
+        if remaining == 0 || (self.drop_last && remaining < self.batch_size) {
+            return None;
+        }
+        let end = self.current + remaining.min(self.batch_size);
+        let batch = self.order[self.current..end].iter().filter_map(|i| self.data.get_sample(*i)).collect();
+        self.current = end;
+        Some(batch)
     }
 }
 
 pub trait DatasetLoader<D : Datum<SIZE>, const SIZE: usize> {
 
-    fn next(&mut self) -> Option<ClassifiedData<D, SIZE>>;
+    /// Reads the next sample. An error only means this sample is lost; whether the loader can
+    /// go on is up to `has_next`.
+    fn next(&mut self) -> Result<ClassifiedData<D, SIZE>, FileError>;
     fn has_next(&self) -> bool;
 }
 
+/// A loader that can read any of its samples directly, which lets `Dataset::lazy` read
+/// samples only when they are used.
+pub trait RandomAccessLoader<D : Datum<SIZE>, const SIZE: usize> : DatasetLoader<D, SIZE> {
+    /// Number of samples, whether or not they can be read.
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// The classification of sample `index`, without reading its data.
+    fn get_class(&self, index: usize) -> f32;
+    fn load(&mut self, index: usize) -> Result<ClassifiedData<D, SIZE>, FileError>;
+}
+
+/// What a sample should be classified as, or the values a model should produce for it.
+///
+/// ```
+/// # use PotatoNeuralNet::Label;
+/// assert_eq!(Label::Scalar(2.0).target(4), Some(vec![0.0, 0.0, 1.0, 0.0]));
+/// assert_eq!(Label::Vector(vec![0.0, 1.0]).target(3), None);
+/// assert_eq!(Label::multi_hot(&[0, 2], 3), Label::Vector(vec![1.0, 0.0, 1.0]));
+/// assert!(Label::one_hot(1, 3).matches(&[0.1, 0.7, 0.2]));
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Label {
+    /// A class index, or the target of a model with a single output.
+    Scalar(f32),
+    /// A target for every output: one-hot, multi-hot or a regression vector.
+    Vector(Vec<f32>),
+}
+impl Label {
+    pub fn one_hot(class: usize, classes: usize) -> Label {
+        Label::multi_hot(&[class], classes)
+    }
+    pub fn multi_hot(classes: &[usize], width: usize) -> Label {
+        let mut target = vec![0.0; width];
+        for class in classes {
+            if let Some(x) = target.get_mut(*class) {
+                *x = 1.0;
+            }
+        }
+        Label::Vector(target)
+    }
+    /// The values an output of `width` is trained towards. A scalar is turned into a one-hot
+    /// vector, see `target_vector`; a vector is used as it is. `None` if the class isn't one of
+    /// the `width` outputs, or the vector doesn't have `width` entries.
+    pub fn target(&self, width: usize) -> Option<Vec<f32>> {
+        match self {
+            Label::Scalar(class) => crate::training::target_vector(*class, width),
+            Label::Vector(target) => (target.len() == width).then(|| target.clone()),
+        }
+    }
+    /// The label as a single number: the scalar itself, or the position of the largest entry
+    /// of a vector. Used to stratify splits.
+    pub fn class(&self) -> f32 {
+        match self {
+            Label::Scalar(class) => *class,
+            Label::Vector(target) => crate::training::predicted_class(target),
+        }
+    }
+    /// Whether `output` gets the label right.
+    ///
+    /// Scalars and one-hot vectors compare the predicted class, multi-hot vectors need every
+    /// output on the right side of 0.5, and other vectors need every output to round to its
+    /// target.
+    pub fn matches(&self, output: &[f32]) -> bool {
+        let target = match self {
+            Label::Scalar(class) => return crate::training::predicted_class(output) == *class,
+            Label::Vector(target) => target,
+        };
+        if output.len() != target.len() {
+            return false;
+        }
+        let binary = target.iter().all(|t| *t == 0.0 || *t == 1.0);
+        let hot = target.iter().filter(|t| **t == 1.0).count();
+        if binary && hot == 1 {
+            crate::training::predicted_class(output) == self.class()
+        } else if binary {
+            output.iter().zip(target).all(|(o, t)| (*o >= 0.5) == (*t == 1.0))
+        } else {
+            output.iter().zip(target).all(|(o, t)| o.round() == t.round())
+        }
+    }
+}
+impl From<f32> for Label {
+    fn from(class: f32) -> Label {
+        Label::Scalar(class)
+    }
+}
+impl From<Vec<f32>> for Label {
+    fn from(target: Vec<f32>) -> Label {
+        Label::Vector(target)
+    }
+}
+
+#[derive(Clone)]
 pub struct ClassifiedData<D : Datum<SIZE>, const SIZE : usize> {
     data : D,
-    classification : f32
+    label : Label
 }
 impl < D: Datum<SIZE> , const SIZE : usize> ClassifiedData<D, SIZE> {
+    /// `label` can be a `Label`, an `f32` class or a `Vec<f32>` target.
+    pub fn new(data : D, label : impl Into<Label>) -> ClassifiedData<D, SIZE> {
+        ClassifiedData { data, label: label.into() }
+    }
     pub fn get_data(&self) -> &D {
         &self.data
     }
+    pub fn get_label(&self) -> &Label {
+        &self.label
+    }
+    /// The label as a single number, see `Label::class`.
     pub fn get_class(&self) -> f32 {
-        self.classification
+        self.label.class()
     }
 }
 pub trait Datum<const SIZE: usize> : Copy {
@@ -107,73 +712,77 @@ pub trait Datum<const SIZE: usize> : Copy {
     fn from(data: Vec<u8>) -> Option<Self>
     where
         Self: Sized;
+    /// Builds the datum from already decoded numbers, such as the feature columns of a CSV file.
+    /// Defaults to `None`, so loaders of decoded numbers reject every sample.
+    fn from_values(_values: Vec<f32>) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        None
+    }
     fn seed(&self, receiver: Self::ReceiverType);
 }
 use std::path::PathBuf;
-use crate::data_importer::{PNGFileReader, DataReader, BinaryFileReader};
+use crate::data_importer::{PNGFileReader, PngOptions, DataReader, BinaryFileReader, ReadError};
 
 
 pub struct FileSystemLoader {
     paths: Vec<DataItem>,
+    classes: Vec<String>,
     current: usize,
-    root: PathBuf
+    root: PathBuf,
+    png: PngOptions,
 }
 impl<D: Datum<SIZE>, const SIZE: usize> DatasetLoader<D, SIZE>
     for FileSystemLoader
 {
-    fn next(&mut self) -> Option<ClassifiedData<D, SIZE>> {
+    fn next(&mut self) -> Result<ClassifiedData<D, SIZE>, FileError> {
         let c = self.current;
         self.current += 1;
         let mut true_path = self.root.clone();
         true_path.push(&self.paths[c].path);
-
-        let mut file = match File::open(true_path.clone()) {
-            Ok(value) => value,
-            Err(_) => return None,
-        };
-        
-        if let Some("png") = true_path.extension().and_then(OsStr::to_str) {
-            let _reader = PNGFileReader::new(&mut file);
-            if let Ok(mut reader) = _reader {
-                let img = reader.consume();
-                if let Some(value) = D::from(img) {
-                    Some (
-                        ClassifiedData {
-                            data : value,
-                            classification: self.paths[c].classification
-                        }
-                    )
-                }else {
-                    None
-                }
-            }else {
-                None
-            }
-        }else {
-            let mut reader = BinaryFileReader::new(&mut file);
-            let img = reader.consume();
-            if let Some(value) = D::from(img) {
-                Some (
-                    ClassifiedData {
-                        data: value,
-                        classification: self.paths[c].classification
-                    }
-                )
-            }else {
-                None
-            }
-        }
-
+        read_sample(c, &true_path, self.paths[c].classification.clone(), &self.png)
     }
 
     fn has_next(&self) -> bool {
         self.current < self.paths.len()
     }
 }
+impl<D: Datum<SIZE>, const SIZE: usize> RandomAccessLoader<D, SIZE>
+    for FileSystemLoader
+{
+    fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    fn get_class(&self, index: usize) -> f32 {
+        self.paths[index].classification.class()
+    }
+
+    fn load(&mut self, index: usize) -> Result<ClassifiedData<D, SIZE>, FileError> {
+        read_sample(index, &self.root.join(&self.paths[index].path), self.paths[index].classification.clone(), &self.png)
+    }
+}
 
 use std::fs::File;
 use std::io::prelude::*;
 
+/// Reads a sample stored in its own file, decoding PNGs and taking anything else as raw bytes.
+pub(crate) fn read_sample<D: Datum<SIZE>, const SIZE: usize>(index: usize, path: &std::path::Path, label: Label, png: &PngOptions) -> Result<ClassifiedData<D, SIZE>, FileError> {
+    let unreadable = |e| FileError::SampleNotReadable(index, path.display().to_string(), e);
+    let mut file = File::open(path).map_err(|e| unreadable(ReadError::Io(e)))?;
+
+    let img = if let Some("png") = path.extension().and_then(OsStr::to_str) {
+        PNGFileReader::with_options(&mut file, png).map_err(unreadable)?.consume()
+    }else {
+        BinaryFileReader::new(&mut file).consume()
+    };
+    match D::from(img) {
+        Some(data) => Ok(ClassifiedData { data, label }),
+        None => Err(FileError::SampleRejected(index, path.display().to_string())),
+    }
+}
+
 impl FileSystemLoader {
     pub fn new(path: &str) -> Result<FileSystemLoader, FileError> {
         let md = match std::fs::metadata(path) {
@@ -185,55 +794,238 @@ impl FileSystemLoader {
         let mut a = std::path::PathBuf::new();
         a.push(path);
         a.pop();
-        return if md.is_file() {
+        if md.is_file() {
             // assume path points to json or csv containing paths
             if path.ends_with(".json") {
-                let paths = match FileSystemLoader::read_json(path) {
-                    Ok(value) => value,
-                    Err(_) => {
-                        return Result::Err(FileError::IncorrectFormat(path.into(), "json".into()))
-                    }
-                };
-                Result::Ok(FileSystemLoader { paths, current: 0, root: a })
+                let (paths, classes) = FileSystemLoader::read_json(path)?;
+                Result::Ok(FileSystemLoader { paths, classes, current: 0, root: a, png: PngOptions::new() })
+            } else if path.ends_with(".csv") {
+                FileSystemLoader::from_csv(path, true)
             } else {
                 Result::Err(FileError::IncorrectFormat(path.into(), "unknown".into()))
             }
         } else {
             Result::Err(FileError::PathNotDirectoryOrFile(path.into()))
-        };
+        }
+    }
+    /// Reads a CSV index, see `read_csv`. `new` expects CSV indexes to have a header row.
+    pub fn from_csv(path: &str, has_header: bool) -> Result<FileSystemLoader, FileError> {
+        let (paths, classes) = FileSystemLoader::read_csv(path, has_header)?;
+        let mut root = PathBuf::from(path);
+        root.pop();
+        Ok(FileSystemLoader { paths, classes, current: 0, root, png: PngOptions::new() })
     }
-    pub fn read_json(path: &str) -> Result<Vec<DataItem>, FileError> {
+    /// Sets how PNG samples are decoded, e.g. to force a gray 32x32 image for a 1024-wide `Datum`.
+    pub fn with_png_options(self, png: PngOptions) -> Self {
+        FileSystemLoader { png, ..self }
+    }
+    /// Class names, indexed by class. Empty unless the index file names its classes.
+    pub fn get_classes(&self) -> &Vec<String> {
+        &self.classes
+    }
+    pub fn get_class_index(&self, name: &str) -> Option<usize> {
+        self.classes.iter().position(|c| c == name)
+    }
+    /// Reads a manifest of `{"path": .., "classification": ..}` items and the class names
+    /// used in it.
+    ///
+    /// A classification is a number, an array of numbers, or a class name. Names are turned
+    /// into indices by their position in an optional top level `classes` array, or else by
+    /// their sorted order.
+    ///
+    /// ```json
+    /// {
+    ///     "classes": ["cat", "dog"],
+    ///     "data_items": [
+    ///         {"path": "a.png", "classification": "dog"},
+    ///         {"path": "b.png", "classification": [1.0, 0.0]}
+    ///     ]
+    /// }
+    /// ```
+    pub fn read_json(path: &str) -> Result<(Vec<DataItem>, Vec<String>), FileError> {
         let mut file = match File::open(path) {
             Ok(value) => value,
-            Err(_) => return Result::Err(FileError::FileNotReadable(path.into())),
+            Err(e) => return Result::Err(FileError::FileNotReadable(path.into(), e)),
         };
         let mut json_cache = String::new();
-        if let Err(_) = file.read_to_string(&mut json_cache) {
-            return Err(FileError::FileNotReadable(path.into()));
+        if let Err(e) = file.read_to_string(&mut json_cache) {
+            return Err(FileError::FileNotReadable(path.into(), e));
         }
 
         let object: JsonDataset = match serde_json::from_str(&json_cache) {
             Ok(value) => value,
-            Err(_) => return Err(FileError::IncorrectFormat(path.into(), "json".into())),
+            Err(e) => return Err(FileError::InvalidJson(path.into(), e)),
         };
-        Result::Ok(object.data_items)
+        resolve_names(path, object.data_items, object.classes, 0)
+    }
+    /// Reads an index of `path,classification` rows and the class names used in it.
+    ///
+    /// The classification is a number or a class name, numbered by sorted order. Rows with
+    /// more than one classification column get a vector label. With `has_header` the first
+    /// row is skipped, whatever it holds.
+    pub fn read_csv(path: &str, has_header: bool) -> Result<(Vec<DataItem>, Vec<String>), FileError> {
+        let file = match File::open(path) {
+            Ok(value) => value,
+            Err(e) => return Err(FileError::FileNotReadable(path.into(), e)),
+        };
+        let mut reader = csv::ReaderBuilder::new().has_headers(false).flexible(true).from_reader(file);
+        let mut rows = vec![];
+        for record in reader.records() {
+            let record = match record {
+                Ok(value) => value,
+                Err(e) => return Err(FileError::InvalidCsv(path.into(), e)),
+            };
+            let row: Vec<String> = record.iter().map(|x| x.trim().to_string()).collect();
+            if row.len() < 2 {
+                return Err(FileError::IncorrectFormat(path.into(), "csv of path,classification rows".into()));
+            }
+            rows.push(row);
+        }
+
+        let mut items = vec![];
+        for (row, fields) in rows.into_iter().enumerate().skip(has_header as usize) {
+            let classification = if fields.len() == 2 {
+                match fields[1].parse() {
+                    Ok(value) => RawLabel::Scalar(value),
+                    Err(_) => RawLabel::Name(fields[1].clone()),
+                }
+            } else {
+                let mut target = vec![];
+                for value in &fields[1..] {
+                    match value.parse() {
+                        Ok(value) => target.push(value),
+                        Err(_) => return Err(FileError::InvalidValue(path.into(), row, "classification".into(), value.clone())),
+                    }
+                }
+                RawLabel::Vector(target)
+            };
+            items.push(RawDataItem { path: fields[0].clone(), classification });
+        }
+        resolve_names(path, items, None, has_header as usize)
     }
 }
-use serde::Deserialize;
-#[derive(Deserialize)]
+use serde::{Deserialize, Serialize};
 pub struct DataItem {
     path: String,
-    classification: f32,
+    classification: Label,
+}
+/// A label as written in an index file, before class names are turned into indices.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawLabel {
+    Scalar(f32),
+    Vector(Vec<f32>),
+    Name(String),
+}
+#[derive(Deserialize)]
+struct RawDataItem {
+    path: String,
+    classification: RawLabel,
 }
 #[derive(Deserialize)]
 struct JsonDataset {
-    data_items: Vec<DataItem>,
+    data_items: Vec<RawDataItem>,
+    #[serde(default)]
+    classes: Option<Vec<String>>,
+}
+
+/// Turns class names into indices, into `classes` when given or else the sorted names.
+/// `first_row` is the row of the first item in the file, for errors.
+fn resolve_names(path: &str, items: Vec<RawDataItem>, classes: Option<Vec<String>>, first_row: usize) -> Result<(Vec<DataItem>, Vec<String>), FileError> {
+    let classes = classes.unwrap_or_else(|| {
+        let names: BTreeSet<&String> = items.iter().filter_map(|item| match &item.classification {
+            RawLabel::Name(name) => Some(name),
+            _ => None,
+        }).collect();
+        names.into_iter().cloned().collect()
+    });
+    let mut resolved = vec![];
+    for (row, item) in items.into_iter().enumerate() {
+        let classification = match item.classification {
+            RawLabel::Scalar(class) => Label::Scalar(class),
+            RawLabel::Vector(target) => Label::Vector(target),
+            RawLabel::Name(name) => match classes.iter().position(|c| *c == name) {
+                Some(class) => Label::Scalar(class as f32),
+                None => return Err(FileError::InvalidValue(path.into(), first_row + row, "classification".into(), name)),
+            },
+        };
+        resolved.push(DataItem { path: item.path, classification });
+    }
+    Ok((resolved, classes))
 }
 
 #[derive(Debug)]
 pub enum FileError {
     PathNotFound(String),
     PathNotDirectoryOrFile(String),
-    FileNotReadable(String),
+    /// (path, cause) of a file or directory that couldn't be opened or read.
+    FileNotReadable(String, std::io::Error),
+    /// (path, expected format) of a file in the wrong format.
     IncorrectFormat(String, String),
+    /// (path, cause) of a JSON manifest that couldn't be parsed.
+    InvalidJson(String, serde_json::Error),
+    /// (path, cause) of a malformed CSV file.
+    InvalidCsv(String, csv::Error),
+    /// (path, column) of a column that isn't in the file.
+    ColumnNotFound(String, String),
+    /// (path, row, column) of a value that is missing. Rows count from 0 and include any
+    /// header row, for JSON they are the index of the item.
+    MissingValue(String, usize, String),
+    /// (path, row, column, value) of a value that couldn't be parsed, rows as for `MissingValue`.
+    InvalidValue(String, usize, String, String),
+    /// (path, magic number) of a file that doesn't start with the expected magic number.
+    InvalidMagic(String, u32),
+    /// (path, items, labels) when a data file and its label file disagree on the item count.
+    CountMismatch(String, usize, usize),
+    /// (root) of an image folder without any class directories.
+    NoClasses(String),
+    /// (root, class) of a class directory without any usable files.
+    EmptyClass(String, String),
+    /// (sample index, path, cause) of a sample that couldn't be read.
+    SampleNotReadable(usize, String, ReadError),
+    /// (sample index, path) of a sample the `Datum` rejected, usually for having the wrong size.
+    SampleRejected(usize, String),
+    /// (sample index, width, expected width) of a vector label with another width than the
+    /// labels before it, or than the model for the first label a lazy dataset reads.
+    LabelWidthMismatch(usize, usize, usize),
+    /// (label width, outputs) when the vector labels of a dataset don't fit the model.
+    OutputWidthMismatch(usize, usize),
+    /// (sample index, class, classes) of a class label that isn't a whole number below the
+    /// number of classes the model has.
+    InvalidClass(usize, f32, usize),
+}
+impl std::fmt::Display for FileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileError::PathNotFound(path) => write!(f, "{path}: not found"),
+            FileError::PathNotDirectoryOrFile(path) => write!(f, "{path}: not a file or directory"),
+            FileError::FileNotReadable(path, e) => write!(f, "{path}: could not be read: {e}"),
+            FileError::IncorrectFormat(path, format) => write!(f, "{path}: expected {format}"),
+            FileError::InvalidJson(path, e) => write!(f, "{path}: invalid JSON: {e}"),
+            FileError::InvalidCsv(path, e) => write!(f, "{path}: invalid CSV: {e}"),
+            FileError::ColumnNotFound(path, column) => write!(f, "{path}: no column {column}"),
+            FileError::MissingValue(path, row, column) => write!(f, "{path}: row {row} has no value for {column}"),
+            FileError::InvalidValue(path, row, column, value) => write!(f, "{path}: row {row} has an invalid value {value:?} for {column}"),
+            FileError::InvalidMagic(path, magic) => write!(f, "{path}: unexpected magic number {magic:#010x}"),
+            FileError::CountMismatch(path, items, labels) => write!(f, "{path}: {items} items but {labels} labels"),
+            FileError::NoClasses(root) => write!(f, "{root}: no class directories"),
+            FileError::EmptyClass(root, class) => write!(f, "{root}: class {class} has no files"),
+            FileError::SampleNotReadable(index, path, e) => write!(f, "{path}: sample {index} could not be read: {e}"),
+            FileError::SampleRejected(index, path) => write!(f, "{path}: sample {index} was rejected by the datum"),
+            FileError::LabelWidthMismatch(index, width, expected) => write!(f, "sample {index} has a label of {width} values instead of {expected}"),
+            FileError::OutputWidthMismatch(width, outputs) => write!(f, "the labels have {width} values but the model has {outputs} outputs"),
+            FileError::InvalidClass(index, class, classes) => write!(f, "sample {index} has class {class}, which isn't one of the {classes} classes"),
+        }
+    }
+}
+impl std::error::Error for FileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FileError::FileNotReadable(_, e) => Some(e),
+            FileError::InvalidJson(_, e) => Some(e),
+            FileError::InvalidCsv(_, e) => Some(e),
+            FileError::SampleNotReadable(_, _, e) => Some(e),
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,218 @@
+use crate::data_importer::ReadError;
+use crate::data_set::{ClassifiedData, DatasetLoader, Datum, FileError};
+use std::fs::File;
+use std::io::{BufReader, prelude::*};
+
+/// Element type of an IDX file, from the third byte of its magic number.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IdxType {
+    U8,
+    I8,
+    I16,
+    I32,
+    F32,
+    F64,
+}
+impl IdxType {
+    fn from_code(code : u8) -> Option<IdxType> {
+        match code {
+            0x08 => Some(IdxType::U8),
+            0x09 => Some(IdxType::I8),
+            0x0B => Some(IdxType::I16),
+            0x0C => Some(IdxType::I32),
+            0x0D => Some(IdxType::F32),
+            0x0E => Some(IdxType::F64),
+            _ => None,
+        }
+    }
+    /// Size of one element in bytes.
+    pub fn width(&self) -> usize {
+        match self {
+            IdxType::U8 | IdxType::I8 => 1,
+            IdxType::I16 => 2,
+            IdxType::I32 | IdxType::F32 => 4,
+            IdxType::F64 => 8,
+        }
+    }
+    /// Decodes one big endian element.
+    fn decode(&self, bytes : &[u8]) -> f32 {
+        match self {
+            IdxType::U8 => bytes[0] as f32,
+            IdxType::I8 => bytes[0] as i8 as f32,
+            IdxType::I16 => i16::from_be_bytes([bytes[0], bytes[1]]) as f32,
+            IdxType::I32 => i32::from_be_bytes(bytes[..4].try_into().unwrap()) as f32,
+            IdxType::F32 => f32::from_be_bytes(bytes[..4].try_into().unwrap()),
+            IdxType::F64 => f64::from_be_bytes(bytes[..8].try_into().unwrap()) as f32,
+        }
+    }
+}
+
+/// Header of an IDX file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IdxHeader {
+    pub data_type : IdxType,
+    /// The first dimension is the number of items.
+    pub dimensions : Vec<usize>,
+}
+impl IdxHeader {
+    pub fn items(&self) -> usize {
+        self.dimensions.first().copied().unwrap_or(0)
+    }
+    /// Number of elements in one item, e.g. 784 for 28x28 MNIST images.
+    pub fn item_len(&self) -> usize {
+        self.dimensions.iter().skip(1).fold(1, |len, d| len.saturating_mul(*d))
+    }
+    /// Bytes of data after the header, `None` if that doesn't fit in a `u64`.
+    fn data_len(&self) -> Option<u64> {
+        self.dimensions.iter().try_fold(self.data_type.width() as u64, |len, d| len.checked_mul(*d as u64))
+    }
+    fn header_len(&self) -> u64 {
+        4 + 4 * self.dimensions.len() as u64
+    }
+    fn read(path : &str, file : &mut File) -> Result<IdxHeader, FileError> {
+        let mut magic = [0u8; 4];
+        file.read_exact(&mut magic).map_err(|_| FileError::IncorrectFormat(path.into(), "idx".into()))?;
+        let data_type = IdxType::from_code(magic[2]);
+        let (0, 0, Some(data_type), 1..) = (magic[0], magic[1], data_type, magic[3]) else {
+            return Err(FileError::InvalidMagic(path.into(), u32::from_be_bytes(magic)));
+        };
+        let mut dimensions = vec![];
+        for _ in 0..magic[3] {
+            let mut dim = [0u8; 4];
+            file.read_exact(&mut dim).map_err(|_| FileError::IncorrectFormat(path.into(), "idx".into()))?;
+            dimensions.push(u32::from_be_bytes(dim) as usize);
+        }
+        let header = IdxHeader { data_type, dimensions };
+        // a corrupt header can claim sizes that overflow, which no real file has
+        let too_large = || FileError::IncorrectFormat(path.into(), format!("idx of an addressable size, not dimensions {:?}", header.dimensions));
+        let item_bytes = header.dimensions.iter().skip(1).try_fold(data_type.width(), |len, d| len.checked_mul(*d));
+        let data_len = header.data_len().filter(|_| item_bytes.is_some()).ok_or_else(too_large)?;
+        // catches truncated downloads before any sample is read
+        let expected = header.header_len().checked_add(data_len).ok_or_else(too_large)?;
+        let actual = file.metadata().map_err(|e| FileError::FileNotReadable(path.into(), e))?.len();
+        if actual != expected {
+            return Err(FileError::IncorrectFormat(path.into(), format!("idx with {expected} bytes, found {actual}")));
+        }
+        Ok(header)
+    }
+}
+
+/// Reads the IDX files MNIST and Fashion-MNIST are distributed in (after decompressing).
+///
+/// Items are streamed from the image file. `u8` images are passed to `Datum::from` as the
+/// raw bytes, any other element type is converted and passed to `Datum::from_values`.
+///
+/// ```no_run
+/// # use PotatoNeuralNet::IdxLoader;
+/// let loader = IdxLoader::new("./mnist/train-images-idx3-ubyte", "./mnist/train-labels-idx1-ubyte").unwrap();
+/// assert_eq!(loader.get_images_header().item_len(), 784);
+/// ```
+pub struct IdxLoader {
+    images_path : String,
+    images : BufReader<File>,
+    images_header : IdxHeader,
+    labels : Vec<f32>,
+    current : usize,
+}
+impl IdxLoader {
+    /// Reads both headers and the labels, and checks that the files agree on the item count.
+    ///
+    /// ```
+    /// # use PotatoNeuralNet::{ClassifiedData, DatasetLoader, Datum, FileError, IdxLoader, Label};
+    /// # use std::{cell::RefCell, rc::Rc};
+    /// # #[derive(Copy, Clone)]
+    /// # struct Pixels([f32; 4]);
+    /// # impl Datum<4> for Pixels {
+    /// #     type DataType = f32;
+    /// #     type ReceiverType = Rc<RefCell<[f32; 4]>>;
+    /// #     fn from(data: Vec<u8>) -> Option<Self> { Self::from_values(data.into_iter().map(f32::from).collect()) }
+    /// #     fn from_values(values: Vec<f32>) -> Option<Self> { Some(Pixels(values.try_into().ok()?)) }
+    /// #     fn seed(&self, receiver: Self::ReceiverType) { *receiver.borrow_mut() = self.0; }
+    /// # }
+    /// # fn items(mut loader: IdxLoader) -> Vec<([f32; 4], Label)> {
+    /// #     let mut items = vec![];
+    /// #     while DatasetLoader::<Pixels, 4>::has_next(&loader) {
+    /// #         let sample : ClassifiedData<Pixels, 4> = loader.next().unwrap();
+    /// #         items.push((sample.get_data().0, sample.get_label().clone()));
+    /// #     }
+    /// #     items
+    /// # }
+    /// let dir = std::env::temp_dir().join(format!("potato_idx_{}", std::process::id()));
+    /// std::fs::create_dir_all(&dir).unwrap();
+    /// let write = |name : &str, bytes : &[&[u8]]| {
+    ///     let path = dir.join(name);
+    ///     std::fs::write(&path, bytes.concat()).unwrap();
+    ///     path.to_str().unwrap().to_string()
+    /// };
+    /// // two 2x2 u8 images and their labels
+    /// let images = write("images", &[&[0, 0, 8, 3], &[0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0, 2], &[0, 1, 2, 3, 255, 254, 253, 252]]);
+    /// let labels = write("labels", &[&[0, 0, 8, 1], &[0, 0, 0, 2], &[7, 3]]);
+    /// assert_eq!(items(IdxLoader::new(&images, &labels).unwrap()), vec![
+    ///     ([0.0, 1.0, 2.0, 3.0], Label::Scalar(7.0)),
+    ///     ([255.0, 254.0, 253.0, 252.0], Label::Scalar(3.0)),
+    /// ]);
+    ///
+    /// // other element types are decoded big endian, here i16
+    /// let wide = write("wide", &[&[0, 0, 0x0B, 2], &[0, 0, 0, 2, 0, 0, 0, 4], &[0, 1, 0xFF, 0xFF, 1, 0, 0, 0], &[0x80, 0, 0, 2, 0, 3, 0, 4]]);
+    /// assert_eq!(items(IdxLoader::new(&wide, &labels).unwrap())[..], [
+    ///     ([1.0, -1.0, 256.0, 0.0], Label::Scalar(7.0)),
+    ///     ([-32768.0, 2.0, 3.0, 4.0], Label::Scalar(3.0)),
+    /// ]);
+    ///
+    /// // e.g. a gzipped file that wasn't decompressed
+    /// let zipped = write("zipped", &[&[0x1F, 0x8B, 8, 0], &[0; 8]]);
+    /// assert!(matches!(IdxLoader::new(&zipped, &labels), Err(FileError::InvalidMagic(_, 0x1F8B0800))));
+    ///
+    /// let three_labels = write("three_labels", &[&[0, 0, 8, 1], &[0, 0, 0, 3], &[7, 3, 1]]);
+    /// assert!(matches!(IdxLoader::new(&images, &three_labels), Err(FileError::CountMismatch(_, 2, 3))));
+    ///
+    /// let truncated = write("truncated", &[&[0, 0, 8, 3], &[0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0, 2], &[0, 1, 2, 3, 255]]);
+    /// assert!(matches!(IdxLoader::new(&truncated, &labels), Err(FileError::IncorrectFormat(..))));
+    /// std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn new(images_path : &str, labels_path : &str) -> Result<IdxLoader, FileError> {
+        let mut images = File::open(images_path).map_err(|e| FileError::FileNotReadable(images_path.into(), e))?;
+        let images_header = IdxHeader::read(images_path, &mut images)?;
+        let mut labels_file = File::open(labels_path).map_err(|e| FileError::FileNotReadable(labels_path.into(), e))?;
+        let labels_header = IdxHeader::read(labels_path, &mut labels_file)?;
+        if labels_header.dimensions.len() != 1 {
+            return Err(FileError::IncorrectFormat(labels_path.into(), "idx labels with one dimension".into()));
+        }
+        if labels_header.items() != images_header.items() {
+            return Err(FileError::CountMismatch(images_path.into(), images_header.items(), labels_header.items()));
+        }
+
+        let mut raw = vec![];
+        labels_file.read_to_end(&mut raw).map_err(|e| FileError::FileNotReadable(labels_path.into(), e))?;
+        let labels = raw.chunks_exact(labels_header.data_type.width()).map(|c| labels_header.data_type.decode(c)).collect();
+        Ok(IdxLoader { images_path: images_path.into(), images: BufReader::new(images), images_header, labels, current: 0 })
+    }
+    pub fn get_images_header(&self) -> &IdxHeader {
+        &self.images_header
+    }
+}
+impl <D : Datum<SIZE>, const SIZE : usize> DatasetLoader<D, SIZE> for IdxLoader {
+    fn next(&mut self) -> Result<ClassifiedData<D, SIZE>, FileError> {
+        let index = self.current;
+        let label = self.labels[index];
+        self.current += 1;
+        let data_type = self.images_header.data_type;
+        let mut item = vec![0u8; self.images_header.item_len() * data_type.width()];
+        if let Err(e) = self.images.read_exact(&mut item) {
+            // the stream is broken, later items can't be read either
+            self.current = self.labels.len();
+            return Err(FileError::SampleNotReadable(index, self.images_path.clone(), ReadError::Io(e)));
+        }
+        let datum = if data_type == IdxType::U8 {
+            D::from(item)
+        } else {
+            D::from_values(item.chunks_exact(data_type.width()).map(|c| data_type.decode(c)).collect())
+        };
+        datum.map(|data| ClassifiedData::new(data, label))
+            .ok_or_else(|| FileError::SampleRejected(index, self.images_path.clone()))
+    }
+
+    fn has_next(&self) -> bool {
+        self.current < self.labels.len()
+    }
+}
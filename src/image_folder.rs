@@ -0,0 +1,162 @@
+use crate::data_importer::PngOptions;
+use crate::data_set::{read_sample, ClassifiedData, DatasetLoader, Datum, FileError, RandomAccessLoader};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+/// Loads a dataset laid out as one directory per class:
+///
+/// ```text
+/// root/cat/0001.png
+/// root/cat/more/0002.png
+/// root/dog/0001.png
+/// ```
+///
+/// Classes are numbered from 0 in the sorted order of their directory names, so the numbering
+/// is the same on every machine. Files in nested directories belong to the class directory
+/// they are under. Hidden files and directories (starting with `.`) are skipped. Files are
+/// read like in `FileSystemLoader`: PNGs are decoded, anything else is taken as raw bytes.
+///
+/// ```no_run
+/// # use PotatoNeuralNet::ImageFolderLoader;
+/// let loader = ImageFolderLoader::with_extensions("./dataset/train", &["png"]).unwrap();
+/// println!("dog is class {:?}", loader.get_class_index("dog"));
+/// ```
+pub struct ImageFolderLoader {
+    items : Vec<(PathBuf, f32)>,
+    classes : Vec<String>,
+    current : usize,
+    png : PngOptions,
+}
+impl ImageFolderLoader {
+    /// Uses every file under the class directories.
+    pub fn new(root : &str) -> Result<ImageFolderLoader, FileError> {
+        Self::scan(root, None)
+    }
+    /// Only uses files with one of `extensions` (without the dot, case insensitive).
+    pub fn with_extensions(root : &str, extensions : &[&str]) -> Result<ImageFolderLoader, FileError> {
+        let extensions : Vec<String> = extensions.iter().map(|e| e.to_ascii_lowercase()).collect();
+        Self::scan(root, Some(&extensions))
+    }
+    /// Sets how PNG files are decoded.
+    pub fn with_png_options(self, png : PngOptions) -> Self {
+        ImageFolderLoader { png, ..self }
+    }
+    /// Class names, indexed by class.
+    pub fn get_classes(&self) -> &Vec<String> {
+        &self.classes
+    }
+    pub fn get_class_index(&self, name : &str) -> Option<usize> {
+        self.classes.iter().position(|c| c == name)
+    }
+    /// Mapping from class name to class index.
+    pub fn get_class_map(&self) -> BTreeMap<String, usize> {
+        self.classes.iter().enumerate().map(|(i, c)| (c.clone(), i)).collect()
+    }
+    /// Number of files found.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    fn scan(root : &str, extensions : Option<&[String]>) -> Result<ImageFolderLoader, FileError> {
+        let md = fs::metadata(root).map_err(|e| FileError::FileNotReadable(root.into(), e))?;
+        if !md.is_dir() {
+            return Err(FileError::PathNotDirectoryOrFile(root.into()));
+        }
+        let mut classes = vec![];
+        for path in sorted_entries(Path::new(root))? {
+            if path.is_dir() {
+                classes.push(path);
+            }
+        }
+        if classes.is_empty() {
+            return Err(FileError::NoClasses(root.into()));
+        }
+
+        let mut names = vec![];
+        let mut items = vec![];
+        for (index, dir) in classes.iter().enumerate() {
+            let name = dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            let mut files = vec![];
+            collect_files(dir, extensions, &mut files, &mut HashSet::new())?;
+            if files.is_empty() {
+                return Err(FileError::EmptyClass(root.into(), name));
+            }
+            for file in files {
+                // catch permission problems now rather than losing the sample later
+                if let Err(e) = File::open(&file) {
+                    return Err(FileError::FileNotReadable(file.display().to_string(), e));
+                }
+                items.push((file, index as f32));
+            }
+            names.push(name);
+        }
+        Ok(ImageFolderLoader { items, classes: names, current: 0, png: PngOptions::new() })
+    }
+}
+impl <D : Datum<SIZE>, const SIZE : usize> DatasetLoader<D, SIZE> for ImageFolderLoader {
+    fn next(&mut self) -> Result<ClassifiedData<D, SIZE>, FileError> {
+        let index = self.current;
+        let (path, class) = &self.items[index];
+        self.current += 1;
+        read_sample(index, path, (*class).into(), &self.png)
+    }
+
+    fn has_next(&self) -> bool {
+        self.current < self.items.len()
+    }
+}
+impl <D : Datum<SIZE>, const SIZE : usize> RandomAccessLoader<D, SIZE> for ImageFolderLoader {
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn get_class(&self, index : usize) -> f32 {
+        self.items[index].1
+    }
+
+    fn load(&mut self, index : usize) -> Result<ClassifiedData<D, SIZE>, FileError> {
+        let (path, class) = &self.items[index];
+        read_sample(index, path, (*class).into(), &self.png)
+    }
+}
+
+/// The visible entries of `dir`, sorted by name.
+fn sorted_entries(dir : &Path) -> Result<Vec<PathBuf>, FileError> {
+    let unreadable = |e| FileError::FileNotReadable(dir.display().to_string(), e);
+    let mut entries = vec![];
+    for entry in fs::read_dir(dir).map_err(unreadable)? {
+        let path = entry.map_err(unreadable)?.path();
+        let hidden = path.file_name().is_some_and(|n| n.as_encoded_bytes().starts_with(b"."));
+        if !hidden {
+            entries.push(path);
+        }
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+/// Every file under `dir`. Symlinked directories are followed, but `visited` keeps a
+/// directory from being entered twice, so symlink cycles end.
+fn collect_files(dir : &Path, extensions : Option<&[String]>, files : &mut Vec<PathBuf>, visited : &mut HashSet<PathBuf>) -> Result<(), FileError> {
+    let canonical = fs::canonicalize(dir).map_err(|e| FileError::FileNotReadable(dir.display().to_string(), e))?;
+    if !visited.insert(canonical) {
+        return Ok(());
+    }
+    for path in sorted_entries(dir)? {
+        if path.is_dir() {
+            collect_files(&path, extensions, files, visited)?;
+        } else if let Some(extensions) = extensions {
+            let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+            if extension.is_some_and(|e| extensions.contains(&e)) {
+                files.push(path);
+            }
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use rand::prelude::*;
+
+/// Closure used by `Initializer::Custom`, called with `(fan_in, fan_out, rng)`.
+pub type InitializerFn = Box<dyn Fn(usize, usize, &mut dyn RngCore) -> f32>;
+
+/// Strategy used to pick the starting weights of a layer.
+///
+/// Every strategy draws from the random number generator it is given, so seeding that
+/// generator makes the initial weights reproducible. `fan_in` is the number of inputs of
+/// the layer (`PREV_SIZE`) and `fan_out` the number of neurons (`SIZE`).
+///
+/// ```
+/// # use PotatoNeuralNet::{ConnectedGenericLayer, InputLayer, Initializer, Relu};
+/// # use std::{cell::RefCell, rc::Rc};
+/// use rand::{rngs::StdRng, SeedableRng};
+/// # let input = Rc::new(RefCell::new(InputLayer::new(Rc::new(RefCell::new([0.0; 4])))));
+/// let mut rng = StdRng::seed_from_u64(42);
+/// let layer : ConnectedGenericLayer<_, Relu, 2, 4> =
+///     ConnectedGenericLayer::with_initializer(input, &Initializer::HeNormal, &mut rng);
+/// ```
+pub enum Initializer {
+    /// Glorot uniform, `U(-a, a)` with `a = sqrt(6 / (fan_in + fan_out))`.
+    XavierUniform,
+    /// Glorot normal, `N(0, 2 / (fan_in + fan_out))`.
+    XavierNormal,
+    /// Kaiming uniform, `U(-a, a)` with `a = sqrt(6 / fan_in)`. Suited to ReLU layers.
+    HeUniform,
+    /// Kaiming normal, `N(0, 2 / fan_in)`. Suited to ReLU layers.
+    HeNormal,
+    /// `U(-a, a)` with `a = sqrt(3 / fan_in)`.
+    LeCunUniform,
+    /// `N(0, 1 / fan_in)`.
+    LeCunNormal,
+    /// A (semi) orthogonal matrix scaled by `gain`, made by orthonormalizing a gaussian matrix.
+    Orthogonal(f32),
+    /// Every weight gets the same value. Note that this leaves all neurons identical.
+    Constant(f32),
+    /// Calls the closure with `(fan_in, fan_out, rng)` once per weight.
+    Custom(InitializerFn),
+}
+
+impl Initializer {
+    /// Creates a `fan_out` by `fan_in` weight matrix.
+    pub fn initialize<R: Rng>(&self, fan_in : usize, fan_out : usize, rng : &mut R) -> Vec<Vec<f32>> {
+        let fan_in_f = fan_in.max(1) as f32;
+        let fan_avg = (fan_in + fan_out).max(1) as f32;
+        match self {
+            Initializer::XavierUniform => uniform(fan_in, fan_out, (6.0 / fan_avg).sqrt(), rng),
+            Initializer::XavierNormal => normal(fan_in, fan_out, (2.0 / fan_avg).sqrt(), rng),
+            Initializer::HeUniform => uniform(fan_in, fan_out, (6.0 / fan_in_f).sqrt(), rng),
+            Initializer::HeNormal => normal(fan_in, fan_out, (2.0 / fan_in_f).sqrt(), rng),
+            Initializer::LeCunUniform => uniform(fan_in, fan_out, (3.0 / fan_in_f).sqrt(), rng),
+            Initializer::LeCunNormal => normal(fan_in, fan_out, (1.0 / fan_in_f).sqrt(), rng),
+            Initializer::Orthogonal(gain) => orthogonal(fan_in, fan_out, *gain, rng),
+            Initializer::Constant(value) => vec![vec![*value; fan_in]; fan_out],
+            Initializer::Custom(f) => {
+                let rng : &mut dyn RngCore = rng;
+                (0..fan_out).map(|_| (0..fan_in).map(|_| f(fan_in, fan_out, &mut *rng)).collect()).collect()
+            }
+        }
+    }
+}
+
+fn uniform<R: Rng>(fan_in : usize, fan_out : usize, limit : f32, rng : &mut R) -> Vec<Vec<f32>> {
+    if limit <= 0.0 {
+        return vec![vec![0.0; fan_in]; fan_out];
+    }
+    (0..fan_out).map(|_| (0..fan_in).map(|_| rng.gen_range(-limit..limit)).collect()).collect()
+}
+
+fn normal<R: Rng>(fan_in : usize, fan_out : usize, std_dev : f32, rng : &mut R) -> Vec<Vec<f32>> {
+    (0..fan_out).map(|_| (0..fan_in).map(|_| gaussian(rng) * std_dev).collect()).collect()
+}
+
+/// Samples the standard normal distribution with the Box-Muller transform.
+pub(crate) fn gaussian<R: Rng>(rng : &mut R) -> f32 {
+    // 1 - [0, 1) keeps the logarithm finite
+    let u1 : f32 = 1.0 - rng.gen::<f32>();
+    let u2 : f32 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+}
+
+fn orthogonal<R: Rng>(fan_in : usize, fan_out : usize, gain : f32, rng : &mut R) -> Vec<Vec<f32>> {
+    // Gram-Schmidt can only produce as many orthonormal vectors as they have entries,
+    // so orthonormalize along the shorter side and transpose if needed.
+    let (rows, cols) = if fan_out <= fan_in { (fan_out, fan_in) } else { (fan_in, fan_out) };
+    let mut basis : Vec<Vec<f32>> = Vec::with_capacity(rows);
+    while basis.len() < rows {
+        let mut v : Vec<f32> = (0..cols).map(|_| gaussian(rng)).collect();
+        for b in basis.iter() {
+            let dot : f32 = v.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+            for (x, y) in v.iter_mut().zip(b.iter()) {
+                *x -= dot * y;
+            }
+        }
+        let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+        // a nearly dependent sample is just drawn again
+        if norm > 1e-4 {
+            basis.push(v.into_iter().map(|x| x / norm).collect());
+        }
+    }
+    if fan_out <= fan_in {
+        basis.into_iter().map(|row| row.into_iter().map(|x| x * gain).collect()).collect()
+    } else {
+        (0..fan_out).map(|i| (0..fan_in).map(|j| basis[j][i] * gain).collect()).collect()
+    }
+}
@@ -1,6 +1,9 @@
 use crate::activation::ActivationFunction;
+use crate::initializer::Initializer;
+use crate::loss::log_sum_exp;
 use crate::model_info::ModelInformation;
-//use crate::optimizer::Optimizer;
+use crate::optimizer::{Optimizer, ParameterKind};
+use crate::serialization::{LayerRecord, ModelError};
 use std::marker::PhantomData;
 
 use rand::prelude::*;
@@ -8,8 +11,35 @@ use rand::prelude::*;
 pub trait Layer {
     fn calculate_state(&mut self);
     fn get_value(&self, idx :usize) -> Option<f32>;
-    // TODO: figure out how to pass the data needed to update smartly
-    fn update(&mut self, info : ModelInformation);
+    /// The number of outputs of the layer.
+    fn get_size(&self) -> usize;
+    /// Every output of the layer, in order.
+    fn get_values(&self) -> Vec<f32> {
+        (0..self.get_size()).map(|i| self.get_value(i).unwrap()).collect()
+    }
+    /// Hands the gradients accumulated by `backpropagate` to the optimizer, which updates
+    /// the parameters, then clears them. Updates the previous layers first.
+    ///
+    /// When `backpropagate` was called for several samples (a mini-batch) since the last
+    /// update, the gradients are averaged over them.
+    ///
+    /// Use `Optimizer::update_layer` rather than calling this directly.
+    fn update(&mut self, info : ModelInformation, optimizer : &mut dyn Optimizer);
+    /// Randomly nudges every parameter by up to the learning rate. Useful for
+    /// training without gradients, e.g. by hill climbing.
+    fn perturb(&mut self, info : ModelInformation);
+    /// Takes the gradient of the loss with respect to this layer's outputs, accumulates
+    /// the gradients of the layer's parameters and passes the gradient with respect to its
+    /// inputs on to the previous layer.
+    ///
+    /// Must be called after `calculate_state`, while the cached state is still current.
+    /// Panics if `gradient` doesn't have an entry for every output.
+    fn backpropagate(&mut self, gradient : &[f32]);
+    /// Appends the records of the previous layers, then the record of this layer, to `records`.
+    fn export(&self, records : &mut Vec<LayerRecord>);
+    /// Restores this layer from the last of `records` and the previous layers from the rest.
+    /// The records must come from `export` on a stack with the same architecture.
+    fn import(&mut self, records : &[LayerRecord]) -> Result<(), ModelError>;
 }
 use std::cell::{RefCell};
 use std::rc::Rc;
@@ -18,74 +48,263 @@ use std::rc::Rc;
 pub struct InputLayer <const SIZE: usize>{
     data : Rc<RefCell<[f32; SIZE]>>,
 }
-impl <'a, const SIZE: usize> Layer for InputLayer<SIZE> {
+impl <const SIZE: usize> Layer for InputLayer<SIZE> {
     fn calculate_state(&mut self) {
         //do nothing
     }
     fn get_value(&self, idx: usize) -> Option<f32> {
-        if idx > SIZE {
+        if idx >= SIZE {
             Option::None
         }else {
 
-            Option::Some(self.data.borrow()[idx].clone())
+            Option::Some(self.data.borrow()[idx])
         }
     }
+    fn get_size(&self) -> usize {
+        SIZE
+    }
 
-    fn update(&mut self, _info: ModelInformation) {
+    fn update(&mut self, _info: ModelInformation, _optimizer: &mut dyn Optimizer) {
+        // do nothing (terminal layer)
+    }
+    fn perturb(&mut self, _info: ModelInformation) {
+        // do nothing (terminal layer)
+    }
+    fn backpropagate(&mut self, _gradient: &[f32]) {
         // do nothing (terminal layer)
     }
+    fn export(&self, records: &mut Vec<LayerRecord>) {
+        records.push(LayerRecord::Input { size: SIZE });
+    }
+    fn import(&mut self, records: &[LayerRecord]) -> Result<(), ModelError> {
+        match records {
+            [LayerRecord::Input { size }] if *size == SIZE => Ok(()),
+            [LayerRecord::Input { size }] => Err(ModelError::ArchitectureMismatch(format!("input of size {size}, expected {SIZE}"))),
+            [] => Err(ModelError::ArchitectureMismatch("missing the input layer".into())),
+            _ => Err(ModelError::ArchitectureMismatch(format!("expected an input layer of size {SIZE}"))),
+        }
+    }
 }
-impl <'a, const SIZE: usize> InputLayer< SIZE> {
+impl <const SIZE: usize> InputLayer< SIZE> {
     pub fn new(data_in : Rc<RefCell<[f32; SIZE]>>) -> InputLayer<SIZE>{
         InputLayer { data: data_in }
     }
 }
+/// A fully connected layer: every output is the activation of a weighted sum of all the
+/// outputs of the previous layer, plus a bias.
+///
+/// Its backpropagation matches a finite difference estimate, here for the weights of a
+/// hidden layer:
+///
+/// ```
+/// # use PotatoNeuralNet::{ConnectedGenericLayer, InputLayer, Layer, LayerRecord, Linear, LossFunction, MeanSquaredError, ModelInformation, Optimizer, Sgd, Tanh};
+/// # use std::{cell::RefCell, rc::Rc};
+/// let input = Rc::new(RefCell::new(InputLayer::new(Rc::new(RefCell::new([0.5, -1.0, 2.0])))));
+/// let hidden : Rc<RefCell<ConnectedGenericLayer<_, Tanh, 4, 3>>> = Rc::new(RefCell::new(ConnectedGenericLayer::new(input)));
+/// let mut output : ConnectedGenericLayer<_, Linear, 2, 4> = ConnectedGenericLayer::new(hidden.clone());
+/// let target = [0.3, -0.7];
+/// let mut records = vec![];
+/// output.export(&mut records);
+///
+/// // the loss with weight (i, j) of the hidden layer moved by h
+/// let mut loss = |i : usize, j : usize, h : f32| {
+///     let mut moved = records.clone();
+///     if let LayerRecord::Connected { fibers, .. } = &mut moved[1] {
+///         fibers[i][j] += h;
+///     }
+///     output.import(&moved).unwrap();
+///     output.calculate_state();
+///     MeanSquaredError.loss(&output.get_values(), &target)
+/// };
+/// let numeric : Vec<Vec<f32>> = (0..4).map(|i| (0..3).map(|j| (loss(i, j, 1e-2) - loss(i, j, -1e-2)) / 2e-2).collect()).collect();
+///
+/// output.import(&records).unwrap();
+/// output.calculate_state();
+/// let (_, gradient) = MeanSquaredError.evaluate(&output.get_values(), &target);
+/// output.backpropagate(&gradient);
+/// // plain gradient descent with a learning rate of 1 subtracts the gradient
+/// let before = hidden.borrow().get_fibers().clone();
+/// Sgd::new().update_layer(&mut output, ModelInformation::new(1.0, 1.0));
+/// let after = hidden.borrow().get_fibers().clone();
+/// for i in 0..4 {
+///     for j in 0..3 {
+///         let analytic = before[i][j] - after[i][j];
+///         assert!((numeric[i][j] - analytic).abs() < 1e-3, "{} != {analytic}", numeric[i][j]);
+///     }
+/// }
+/// ```
 pub struct ConnectedGenericLayer <L : Layer,A: ActivationFunction, const SIZE: usize, const PREV_SIZE: usize> {
     prev_layer : Rc<RefCell<L>>,
     cache_data : [f32; SIZE],
+    // weighted sums before activation, kept for the backward pass
+    cache_sum : [f32; SIZE],
     fibers: Vec<Vec<f32>>,
+    gradients: Vec<Vec<f32>>,
+    biases: Vec<f32>,
+    bias_gradients: Vec<f32>,
+    // samples backpropagated since the last update
+    batch_count: usize,
+    // disabled for layers that feed into a normalization, which would cancel it out anyway
+    use_bias: bool,
     a : PhantomData<A>
 }
 
-impl <'a, L, A, const SIZE: usize, const PREV_SIZE: usize> Layer for ConnectedGenericLayer<L, A, SIZE, PREV_SIZE> where
+impl <L, A, const SIZE: usize, const PREV_SIZE: usize> Layer for ConnectedGenericLayer<L, A, SIZE, PREV_SIZE> where
     L : Layer,
     A: ActivationFunction,
 {
     fn calculate_state(&mut self) {
         self.prev_layer.borrow_mut().calculate_state();
         for i in 0..SIZE {
-            let mut sum = 0.0;
+            let mut sum = if self.use_bias { self.biases[i] } else { 0.0 };
             for j in 0..PREV_SIZE {
                 sum += self.prev_layer.borrow_mut().get_value(j).unwrap() * self.fibers[i][j];
             }
+            self.cache_sum[i] = sum;
             self.cache_data[i] = A::activate(sum);
         }
     }
     fn get_value(&self, idx : usize) -> Option<f32> {
-        if idx > SIZE {
+        if idx >= SIZE {
             Option::None
         }else{
             Option::Some(self.cache_data[idx])
         }
     }
+    fn get_size(&self) -> usize {
+        SIZE
+    }
 
-    fn update(&mut self, info: ModelInformation) {
-        self.prev_layer.borrow_mut().update(info);
+    fn update(&mut self, info: ModelInformation, optimizer: &mut dyn Optimizer) {
+        self.prev_layer.borrow_mut().update(info, optimizer);
+
+        let learning_rate = info.get_lr();
+        let scale = 1.0 / self.batch_count.max(1) as f32;
+        for (row, gradient) in self.fibers.iter_mut().zip(self.gradients.iter_mut()) {
+            gradient.iter_mut().for_each(|g| *g *= scale);
+            optimizer.step(row, gradient, ParameterKind::Weight, learning_rate);
+            gradient.fill(0.0);
+        }
+        if self.use_bias {
+            self.bias_gradients.iter_mut().for_each(|g| *g *= scale);
+            optimizer.step(&mut self.biases, &self.bias_gradients, ParameterKind::Bias, learning_rate);
+            self.bias_gradients.fill(0.0);
+        }
+        self.batch_count = 0;
+    }
+
+    fn perturb(&mut self, info: ModelInformation) {
+        self.prev_layer.borrow_mut().perturb(info);
 
         let learning_rate = info.get_lr();
         let mut rng = thread_rng();
-        for i in 0..SIZE {
-            for j in 0..PREV_SIZE {
-                self.fibers[i][j] += rng.gen_range(-1.0..1.0) * learning_rate;
+        for row in self.fibers.iter_mut() {
+            for fiber in row.iter_mut() {
+                *fiber += rng.gen_range(-1.0..1.0) * learning_rate;
+            }
+        }
+        if self.use_bias {
+            for bias in self.biases.iter_mut() {
+                *bias += rng.gen_range(-1.0..1.0) * learning_rate;
             }
         }
     }
+
+    fn backpropagate(&mut self, gradient: &[f32]) {
+        assert_eq!(gradient.len(), SIZE, "a gradient of {} values for a layer of size {SIZE}", gradient.len());
+        let inputs : Vec<f32> = {
+            let prev = self.prev_layer.borrow();
+            (0..PREV_SIZE).map(|j| prev.get_value(j).unwrap()).collect()
+        };
+        let mut input_gradient = vec![0.0; PREV_SIZE];
+        self.batch_count += 1;
+        for (i, (g, sum)) in gradient.iter().zip(self.cache_sum.iter()).enumerate() {
+            let delta = g * A::derivative(*sum);
+            if self.use_bias {
+                self.bias_gradients[i] += delta;
+            }
+            for (j, input) in inputs.iter().enumerate() {
+                self.gradients[i][j] += delta * input;
+                input_gradient[j] += delta * self.fibers[i][j];
+            }
+        }
+        self.prev_layer.borrow_mut().backpropagate(&input_gradient);
+    }
+
+    fn export(&self, records: &mut Vec<LayerRecord>) {
+        self.prev_layer.borrow().export(records);
+        records.push(LayerRecord::Connected {
+            size: SIZE,
+            inputs: PREV_SIZE,
+            activation: A::name().into(),
+            use_bias: self.use_bias,
+            fibers: self.fibers.clone(),
+            biases: self.biases.clone(),
+        });
+    }
+
+    fn import(&mut self, records: &[LayerRecord]) -> Result<(), ModelError> {
+        let Some((LayerRecord::Connected { size, inputs, activation, use_bias, fibers, biases }, rest)) = records.split_last() else {
+            return Err(ModelError::ArchitectureMismatch(format!("expected a connected layer of size {SIZE}")));
+        };
+        if *size != SIZE || *inputs != PREV_SIZE {
+            return Err(ModelError::ArchitectureMismatch(format!("connected layer {size}x{inputs}, expected {SIZE}x{PREV_SIZE}")));
+        }
+        if activation != A::name() {
+            return Err(ModelError::ArchitectureMismatch(format!("activation {activation}, expected {}", A::name())));
+        }
+        if fibers.len() != SIZE || fibers.iter().any(|row| row.len() != PREV_SIZE) || biases.len() != SIZE {
+            return Err(ModelError::Format(format!("parameters of the {SIZE}x{PREV_SIZE} layer have the wrong shape")));
+        }
+        self.prev_layer.borrow_mut().import(rest)?;
+        self.fibers = fibers.clone();
+        self.biases = biases.clone();
+        self.use_bias = *use_bias;
+        self.gradients.iter_mut().for_each(|row| row.fill(0.0));
+        self.bias_gradients.fill(0.0);
+        self.batch_count = 0;
+        Ok(())
+    }
 }
-impl <'a, L, A, const SIZE: usize, const PREV_SIZE: usize> ConnectedGenericLayer< L, A, SIZE, PREV_SIZE> where
+
+impl <L, A, const SIZE: usize, const PREV_SIZE: usize> ConnectedGenericLayer< L, A, SIZE, PREV_SIZE> where
     L: Layer,
     A: ActivationFunction {
+    /// Creates a layer with Xavier uniform weights drawn from `thread_rng`.
     pub fn new(prev_layer : Rc<RefCell<L>>) -> ConnectedGenericLayer<L, A, SIZE, PREV_SIZE>{
-        ConnectedGenericLayer { prev_layer, cache_data: [0.0; SIZE], fibers: vec![vec![1.0;PREV_SIZE];SIZE], a: PhantomData}
+        Self::with_initializer(prev_layer, &Initializer::XavierUniform, &mut thread_rng())
+    }
+    /// Creates a layer with weights picked by `initializer`. The biases start at zero.
+    pub fn with_initializer<R: Rng>(prev_layer : Rc<RefCell<L>>, initializer : &Initializer, rng : &mut R) -> ConnectedGenericLayer<L, A, SIZE, PREV_SIZE>{
+        ConnectedGenericLayer {
+            prev_layer,
+            cache_data: [0.0; SIZE],
+            cache_sum: [0.0; SIZE],
+            fibers: initializer.initialize(PREV_SIZE, SIZE, rng),
+            gradients: vec![vec![0.0;PREV_SIZE];SIZE],
+            biases: vec![0.0; SIZE],
+            bias_gradients: vec![0.0; SIZE],
+            batch_count: 0,
+            use_bias: true,
+            a: PhantomData
+        }
+    }
+    /// Creates a layer without a bias term, e.g. for layers followed by a normalization.
+    pub fn without_bias(prev_layer : Rc<RefCell<L>>) -> ConnectedGenericLayer<L, A, SIZE, PREV_SIZE>{
+        let mut layer = Self::new(prev_layer);
+        layer.use_bias = false;
+        layer
+    }
+    /// The weights of the layer, one row of `PREV_SIZE` fibers per neuron.
+    pub fn get_fibers(&self) -> &Vec<Vec<f32>> {
+        &self.fibers
+    }
+    /// The bias of each neuron. These stay at zero when the bias is disabled.
+    pub fn get_biases(&self) -> &Vec<f32> {
+        &self.biases
+    }
+    pub fn uses_bias(&self) -> bool {
+        self.use_bias
     }
 }
 impl <L, A, const SIZE: usize, const PREV_SIZE : usize > Clone for ConnectedGenericLayer<L, A, SIZE, PREV_SIZE>
@@ -93,6 +312,246 @@ where
     L : Layer + Clone,
     A : ActivationFunction {
         fn clone(&self) -> Self {
-        Self { prev_layer: Rc::new(RefCell::new(self.prev_layer.borrow().clone())), cache_data: self.cache_data.clone(), fibers: self.fibers.clone(), a: self.a.clone() }
+        Self {
+            prev_layer: Rc::new(RefCell::new(self.prev_layer.borrow().clone())),
+            cache_data: self.cache_data,
+            cache_sum: self.cache_sum,
+            fibers: self.fibers.clone(),
+            gradients: self.gradients.clone(),
+            biases: self.biases.clone(),
+            bias_gradients: self.bias_gradients.clone(),
+            batch_count: self.batch_count,
+            use_bias: self.use_bias,
+            a: self.a
+        }
+    }
+    }
+
+/// Reads the outputs of `prev` and returns them with their log-sum-exp.
+fn logits<L : Layer, const SIZE: usize>(prev : &Rc<RefCell<L>>) -> ([f32; SIZE], f32) {
+    let mut prev = prev.borrow_mut();
+    prev.calculate_state();
+    let mut logits = [0.0; SIZE];
+    for (i, z) in logits.iter_mut().enumerate() {
+        *z = prev.get_value(i).unwrap();
+    }
+    let lse = log_sum_exp(&logits);
+    (logits, lse)
+}
+
+fn import_softmax<L : Layer>(prev : &Rc<RefCell<L>>, records : &[LayerRecord], expected : &LayerRecord, name : &str, size : usize) -> Result<(), ModelError> {
+    match records.split_last() {
+        Some((record, rest)) if record == expected => prev.borrow_mut().import(rest),
+        _ => Err(ModelError::ArchitectureMismatch(format!("expected a {name} layer of size {size}"))),
+    }
+}
+
+/// Turns the outputs of the previous layer into class probabilities that sum to 1.
+///
+/// Unlike an `ActivationFunction`, which sees one value at a time, this normalizes the
+/// whole output. It has no parameters of its own. The exponentials are shifted by the
+/// log-sum-exp of the inputs, so large scores can't overflow.
+///
+/// Paired with `CategoricalCrossEntropy` the loss is capped for probabilities that round to
+/// 0, so for training prefer a `LogSoftmaxLayer` with `NegativeLogLikelihood`.
+///
+/// ```
+/// # use PotatoNeuralNet::{ConnectedGenericLayer, InputLayer, Layer, Linear, SoftmaxLayer};
+/// # use std::{cell::RefCell, rc::Rc};
+/// let input = Rc::new(RefCell::new([0.5; 4]));
+/// let scores : ConnectedGenericLayer<_, Linear, 3, 4> =
+///     ConnectedGenericLayer::new(Rc::new(RefCell::new(InputLayer::new(input))));
+/// let mut output : SoftmaxLayer<_, 3> = SoftmaxLayer::new(Rc::new(RefCell::new(scores)));
+/// output.calculate_state();
+/// assert!((output.get_values().iter().sum::<f32>() - 1.0).abs() < 1e-6);
+/// ```
+///
+/// Its backpropagation matches a finite difference estimate:
+///
+/// ```
+/// # use PotatoNeuralNet::{Layer, LayerRecord, ModelError, ModelInformation, Optimizer, SoftmaxLayer};
+/// # use std::{cell::RefCell, rc::Rc};
+/// # // fixed logits that keep the gradient they receive
+/// # struct Logits { z : [f32; 3], gradient : Vec<f32> }
+/// # impl Layer for Logits {
+/// #     fn calculate_state(&mut self) {}
+/// #     fn get_value(&self, idx : usize) -> Option<f32> { self.z.get(idx).copied() }
+/// #     fn get_size(&self) -> usize { 3 }
+/// #     fn update(&mut self, _ : ModelInformation, _ : &mut dyn Optimizer) {}
+/// #     fn perturb(&mut self, _ : ModelInformation) {}
+/// #     fn backpropagate(&mut self, gradient : &[f32]) { self.gradient = gradient.to_vec(); }
+/// #     fn export(&self, _ : &mut Vec<LayerRecord>) {}
+/// #     fn import(&mut self, _ : &[LayerRecord]) -> Result<(), ModelError> { Ok(()) }
+/// # }
+/// let logits = Rc::new(RefCell::new(Logits { z: [0.3, -1.2, 2.0], gradient: vec![] }));
+/// let mut softmax : SoftmaxLayer<_, 3> = SoftmaxLayer::new(logits.clone());
+/// // any downstream gradient, the loss is its dot product with the probabilities
+/// let upstream = [0.5, -2.0, 1.5];
+/// softmax.calculate_state();
+/// softmax.backpropagate(&upstream);
+/// let analytic = logits.borrow().gradient.clone();
+/// for j in 0..3 {
+///     let mut loss = |h : f32| {
+///         logits.borrow_mut().z[j] += h;
+///         softmax.calculate_state();
+///         logits.borrow_mut().z[j] -= h;
+///         softmax.get_values().iter().zip(upstream).map(|(p, g)| p * g).sum::<f32>()
+///     };
+///     let numeric = (loss(1e-2) - loss(-1e-2)) / 2e-2;
+///     assert!((numeric - analytic[j]).abs() < 1e-3, "{numeric} != {}", analytic[j]);
+/// }
+/// ```
+pub struct SoftmaxLayer <L : Layer, const SIZE: usize> {
+    prev_layer : Rc<RefCell<L>>,
+    cache_data : [f32; SIZE],
+}
+impl <L : Layer, const SIZE: usize> SoftmaxLayer<L, SIZE> {
+    /// Panics if the previous layer doesn't have `SIZE` outputs.
+    pub fn new(prev_layer : Rc<RefCell<L>>) -> SoftmaxLayer<L, SIZE> {
+        let inputs = prev_layer.borrow().get_size();
+        assert_eq!(inputs, SIZE, "a SoftmaxLayer of size {SIZE} needs as many inputs, the previous layer has {inputs}");
+        SoftmaxLayer { prev_layer, cache_data: [0.0; SIZE] }
+    }
+}
+impl <L : Layer, const SIZE: usize> Layer for SoftmaxLayer<L, SIZE> {
+    fn calculate_state(&mut self) {
+        let (logits, lse) = logits::<L, SIZE>(&self.prev_layer);
+        for (p, z) in self.cache_data.iter_mut().zip(logits) {
+            *p = (z - lse).exp();
+        }
     }
-    }
\ No newline at end of file
+    fn get_value(&self, idx : usize) -> Option<f32> {
+        self.cache_data.get(idx).copied()
+    }
+    fn get_size(&self) -> usize {
+        SIZE
+    }
+    fn update(&mut self, info : ModelInformation, optimizer : &mut dyn Optimizer) {
+        self.prev_layer.borrow_mut().update(info, optimizer);
+    }
+    fn perturb(&mut self, info : ModelInformation) {
+        self.prev_layer.borrow_mut().perturb(info);
+    }
+    fn backpropagate(&mut self, gradient : &[f32]) {
+        assert_eq!(gradient.len(), SIZE, "a gradient of {} values for a layer of size {SIZE}", gradient.len());
+        // Jacobian of softmax: dp_i/dz_j = p_i * (δ_ij - p_j)
+        let dot : f32 = gradient.iter().zip(self.cache_data.iter()).map(|(g, p)| g * p).sum();
+        let input_gradient : Vec<f32> = gradient.iter().zip(self.cache_data.iter()).map(|(g, p)| p * (g - dot)).collect();
+        self.prev_layer.borrow_mut().backpropagate(&input_gradient);
+    }
+    fn export(&self, records : &mut Vec<LayerRecord>) {
+        self.prev_layer.borrow().export(records);
+        records.push(LayerRecord::Softmax { size: SIZE });
+    }
+    fn import(&mut self, records : &[LayerRecord]) -> Result<(), ModelError> {
+        import_softmax(&self.prev_layer, records, &LayerRecord::Softmax { size: SIZE }, "softmax", SIZE)
+    }
+}
+impl <L : Layer + Clone, const SIZE: usize> Clone for SoftmaxLayer<L, SIZE> {
+    fn clone(&self) -> Self {
+        Self {
+            prev_layer: Rc::new(RefCell::new(self.prev_layer.borrow().clone())),
+            cache_data: self.cache_data,
+        }
+    }
+}
+
+/// Outputs the logarithm of the softmax of the previous layer, `z_i - ln(sum(e^z))`.
+///
+/// Computed with the log-sum-exp trick it stays finite even for classes whose probability
+/// underflows, which makes it, with `NegativeLogLikelihood`, the stable way to train a
+/// classifier with cross entropy. The predicted class is still the largest output.
+///
+/// With `NegativeLogLikelihood` its backpropagation matches a finite difference estimate:
+///
+/// ```
+/// # use PotatoNeuralNet::{Layer, LayerRecord, LossFunction, ModelError, ModelInformation, LogSoftmaxLayer, NegativeLogLikelihood, Optimizer};
+/// # use std::{cell::RefCell, rc::Rc};
+/// # // fixed logits that keep the gradient they receive
+/// # struct Logits { z : [f32; 3], gradient : Vec<f32> }
+/// # impl Layer for Logits {
+/// #     fn calculate_state(&mut self) {}
+/// #     fn get_value(&self, idx : usize) -> Option<f32> { self.z.get(idx).copied() }
+/// #     fn get_size(&self) -> usize { 3 }
+/// #     fn update(&mut self, _ : ModelInformation, _ : &mut dyn Optimizer) {}
+/// #     fn perturb(&mut self, _ : ModelInformation) {}
+/// #     fn backpropagate(&mut self, gradient : &[f32]) { self.gradient = gradient.to_vec(); }
+/// #     fn export(&self, _ : &mut Vec<LayerRecord>) {}
+/// #     fn import(&mut self, _ : &[LayerRecord]) -> Result<(), ModelError> { Ok(()) }
+/// # }
+/// let logits = Rc::new(RefCell::new(Logits { z: [0.3, -1.2, 2.0], gradient: vec![] }));
+/// let mut output : LogSoftmaxLayer<_, 3> = LogSoftmaxLayer::new(logits.clone());
+/// let target = [0.0, 1.0, 0.0];
+/// output.calculate_state();
+/// let (_, gradient) = NegativeLogLikelihood.evaluate(&output.get_values(), &target);
+/// output.backpropagate(&gradient);
+/// let analytic = logits.borrow().gradient.clone();
+/// for j in 0..3 {
+///     let mut loss = |h : f32| {
+///         logits.borrow_mut().z[j] += h;
+///         output.calculate_state();
+///         logits.borrow_mut().z[j] -= h;
+///         NegativeLogLikelihood.loss(&output.get_values(), &target)
+///     };
+///     let numeric = (loss(1e-2) - loss(-1e-2)) / 2e-2;
+///     assert!((numeric - analytic[j]).abs() < 1e-3, "{numeric} != {}", analytic[j]);
+/// }
+/// ```
+pub struct LogSoftmaxLayer <L : Layer, const SIZE: usize> {
+    prev_layer : Rc<RefCell<L>>,
+    cache_data : [f32; SIZE],
+}
+impl <L : Layer, const SIZE: usize> LogSoftmaxLayer<L, SIZE> {
+    /// Panics if the previous layer doesn't have `SIZE` outputs.
+    pub fn new(prev_layer : Rc<RefCell<L>>) -> LogSoftmaxLayer<L, SIZE> {
+        let inputs = prev_layer.borrow().get_size();
+        assert_eq!(inputs, SIZE, "a LogSoftmaxLayer of size {SIZE} needs as many inputs, the previous layer has {inputs}");
+        LogSoftmaxLayer { prev_layer, cache_data: [0.0; SIZE] }
+    }
+    /// The class probabilities, the exponentials of the outputs.
+    pub fn get_probabilities(&self) -> Vec<f32> {
+        self.cache_data.iter().map(|y| y.exp()).collect()
+    }
+}
+impl <L : Layer, const SIZE: usize> Layer for LogSoftmaxLayer<L, SIZE> {
+    fn calculate_state(&mut self) {
+        let (logits, lse) = logits::<L, SIZE>(&self.prev_layer);
+        for (y, z) in self.cache_data.iter_mut().zip(logits) {
+            *y = z - lse;
+        }
+    }
+    fn get_value(&self, idx : usize) -> Option<f32> {
+        self.cache_data.get(idx).copied()
+    }
+    fn get_size(&self) -> usize {
+        SIZE
+    }
+    fn update(&mut self, info : ModelInformation, optimizer : &mut dyn Optimizer) {
+        self.prev_layer.borrow_mut().update(info, optimizer);
+    }
+    fn perturb(&mut self, info : ModelInformation) {
+        self.prev_layer.borrow_mut().perturb(info);
+    }
+    fn backpropagate(&mut self, gradient : &[f32]) {
+        assert_eq!(gradient.len(), SIZE, "a gradient of {} values for a layer of size {SIZE}", gradient.len());
+        // dy_i/dz_j = δ_ij - p_j
+        let total : f32 = gradient.iter().sum();
+        let input_gradient : Vec<f32> = gradient.iter().zip(self.cache_data.iter()).map(|(g, y)| g - y.exp() * total).collect();
+        self.prev_layer.borrow_mut().backpropagate(&input_gradient);
+    }
+    fn export(&self, records : &mut Vec<LayerRecord>) {
+        self.prev_layer.borrow().export(records);
+        records.push(LayerRecord::LogSoftmax { size: SIZE });
+    }
+    fn import(&mut self, records : &[LayerRecord]) -> Result<(), ModelError> {
+        import_softmax(&self.prev_layer, records, &LayerRecord::LogSoftmax { size: SIZE }, "log-softmax", SIZE)
+    }
+}
+impl <L : Layer + Clone, const SIZE: usize> Clone for LogSoftmaxLayer<L, SIZE> {
+    fn clone(&self) -> Self {
+        Self {
+            prev_layer: Rc::new(RefCell::new(self.prev_layer.borrow().clone())),
+            cache_data: self.cache_data,
+        }
+    }
+}
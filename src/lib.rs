@@ -2,15 +2,43 @@
 //!
 //! Allows you to create architectures and train models in a cumbersome and unnatural way
 //!
+#![allow(non_snake_case)] // the crate name
 
 mod activation;
+mod checkpoint;
+mod cifar_loader;
+mod csv_loader;
 mod data_importer;
 mod data_set;
+mod idx_loader;
+mod image_folder;
+mod initializer;
 mod layers;
+mod loss;
+mod metrics;
+mod mmap_loader;
 mod model_info;
+mod optimizer;
+mod serialization;
+mod training;
+mod training_log;
 
-pub use layers::{Layer, ConnectedGenericLayer, InputLayer};
-pub use activation::ActivationFunction;
-pub use data_importer::{ConsumableType, DataReader, PNGFileReader, BinaryFileReader, ReadError};
-pub use data_set::{DatasetLoader, Dataset, Datum};
-pub use model_info::ModelInformation;
\ No newline at end of file
+pub use layers::{Layer, ConnectedGenericLayer, InputLayer, SoftmaxLayer, LogSoftmaxLayer};
+pub use initializer::{Initializer, InitializerFn};
+pub use activation::{ActivationFunction, Linear, Sigmoid, Tanh, Relu, LeakyRelu, Elu, Gelu, Softplus, Swish, HardTanh};
+pub use data_importer::{ConsumableType, DataReader, PNGFileReader, PngOptions, ColorLayout, SampleFormat, Reshape, BinaryFileReader, Endianness, F16, Bf16, ReadError};
+pub use cifar_loader::{CifarLoader, CifarVariant, CifarRecord, ChannelLayout};
+pub use csv_loader::{CsvLoader, CsvOptions, CsvColumn, MissingValues};
+pub use data_set::{DatasetLoader, Dataset, Split, SplitOptions, SplitStrategy, Batches, DatasetState, Datum, ClassifiedData, Label, FileSystemLoader, DataItem, FileError, LoadReport, RandomAccessLoader};
+pub use idx_loader::{IdxLoader, IdxHeader, IdxType};
+pub use image_folder::ImageFolderLoader;
+pub use metrics::{predict, Predictions, ConfusionMatrix, ClassMetrics, ClassificationReport};
+pub use metrics::{predict_regression, evaluate_regression, RegressionPredictions, RegressionMetrics, RegressionReport, RegressionEvaluation, Histogram};
+pub use mmap_loader::{MmapLoader, PackedLayout, PackedLabel};
+pub use loss::{LossFunction, MeanSquaredError, MeanAbsoluteError, Huber, BinaryCrossEntropy, CategoricalCrossEntropy, Hinge, KlDivergence, NegativeLogLikelihood};
+pub use model_info::{ModelInformation, LearningRateSchedule};
+pub use optimizer::{Optimizer, ParameterKind, Sgd, RmsProp, Adagrad, Adam, AdamW};
+pub use checkpoint::{Checkpoint, CheckpointManager};
+pub use training_log::{LogFormat, LogRecord, TrainingLogger};
+pub use serialization::{save_model, load_model, ModelFile, ModelFormat, LayerRecord, ModelError, MODEL_FORMAT_VERSION};
+pub use training::{train_epoch, train_epoch_with, evaluate, evaluate_split, target_vector, predicted_class, Evaluation, EpochRecord, TrainingHistory};
@@ -0,0 +1,219 @@
+/// Measures how far the output of a network is from the expected output.
+///
+/// Unlike an ActivationFunction, a loss looks at the whole output vector at once, and some
+/// losses carry parameters (e.g. the `delta` of `Huber`), so they are used as values.
+/// `evaluate` returns the loss along with its gradient with respect to every output, which
+/// is what gets passed to `Layer::backpropagate`.
+///
+/// ```
+/// # use PotatoNeuralNet::{LossFunction, MeanSquaredError};
+/// let (loss, gradient) = MeanSquaredError.evaluate(&[1.0, 2.0], &[1.0, 0.0]);
+/// assert_eq!(loss, 2.0);
+/// assert_eq!(gradient, vec![0.0, 2.0]);
+/// ```
+///
+/// The output and the target are compared entry by entry, so their lengths must match:
+///
+/// ```should_panic
+/// # use PotatoNeuralNet::{LossFunction, MeanSquaredError};
+/// MeanSquaredError.evaluate(&[1.0, 2.0, 3.0], &[1.0]);
+/// ```
+pub trait LossFunction {
+    /// Returns the loss and its gradient with respect to `output`. Panics if `output` and
+    /// `target` have different lengths.
+    fn evaluate(&self, output : &[f32], target : &[f32]) -> (f32, Vec<f32>);
+    fn loss(&self, output : &[f32], target : &[f32]) -> f32 {
+        self.evaluate(output, target).0
+    }
+}
+
+// keeps the logarithms in the cross entropy losses finite
+const EPSILON : f32 = 1e-7;
+
+fn check_lengths(output : &[f32], target : &[f32]) {
+    assert_eq!(output.len(), target.len(), "an output of {} values can't be compared with a target of {}", output.len(), target.len());
+}
+
+/// Mean of the squared differences.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MeanSquaredError;
+impl LossFunction for MeanSquaredError {
+    fn evaluate(&self, output : &[f32], target : &[f32]) -> (f32, Vec<f32>) {
+        check_lengths(output, target);
+        let n = output.len().max(1) as f32;
+        let diff : Vec<f32> = output.iter().zip(target).map(|(o, t)| o - t).collect();
+        let loss = diff.iter().map(|d| d * d).sum::<f32>() / n;
+        (loss, diff.into_iter().map(|d| 2.0 * d / n).collect())
+    }
+}
+
+/// Mean of the absolute differences.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MeanAbsoluteError;
+impl LossFunction for MeanAbsoluteError {
+    fn evaluate(&self, output : &[f32], target : &[f32]) -> (f32, Vec<f32>) {
+        check_lengths(output, target);
+        let n = output.len().max(1) as f32;
+        let diff : Vec<f32> = output.iter().zip(target).map(|(o, t)| o - t).collect();
+        let loss = diff.iter().map(|d| d.abs()).sum::<f32>() / n;
+        let gradient = diff.into_iter().map(|d| if d == 0.0 { 0.0 } else { d.signum() / n }).collect();
+        (loss, gradient)
+    }
+}
+
+/// Squared error for differences up to `delta`, absolute error beyond it.
+#[derive(Copy, Clone, Debug)]
+pub struct Huber {
+    pub delta : f32,
+}
+impl Default for Huber {
+    fn default() -> Self {
+        Huber { delta: 1.0 }
+    }
+}
+impl LossFunction for Huber {
+    fn evaluate(&self, output : &[f32], target : &[f32]) -> (f32, Vec<f32>) {
+        check_lengths(output, target);
+        let n = output.len().max(1) as f32;
+        let mut loss = 0.0;
+        let mut gradient = Vec::with_capacity(output.len());
+        for (o, t) in output.iter().zip(target) {
+            let d = o - t;
+            if d.abs() <= self.delta {
+                loss += 0.5 * d * d;
+                gradient.push(d / n);
+            } else {
+                loss += self.delta * (d.abs() - 0.5 * self.delta);
+                gradient.push(self.delta * d.signum() / n);
+            }
+        }
+        (loss / n, gradient)
+    }
+}
+
+/// Cross entropy of independent yes/no outputs. The outputs must be probabilities, e.g.
+/// from a `Sigmoid` layer, and the targets 0 or 1.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct BinaryCrossEntropy;
+impl LossFunction for BinaryCrossEntropy {
+    fn evaluate(&self, output : &[f32], target : &[f32]) -> (f32, Vec<f32>) {
+        check_lengths(output, target);
+        let n = output.len().max(1) as f32;
+        let mut loss = 0.0;
+        let mut gradient = Vec::with_capacity(output.len());
+        for (o, t) in output.iter().zip(target) {
+            let p = o.clamp(EPSILON, 1.0 - EPSILON);
+            loss -= t * p.ln() + (1.0 - t) * (1.0 - p).ln();
+            gradient.push((p - t) / (p * (1.0 - p)) / n);
+        }
+        (loss / n, gradient)
+    }
+}
+
+/// Cross entropy of a distribution over classes, against a (usually one-hot) target
+/// distribution.
+///
+/// With `from_logits` the outputs are taken as unnormalized scores and the softmax is folded
+/// into the loss using the log-sum-exp trick, which is more stable than normalizing first.
+/// Otherwise the outputs must already be probabilities.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CategoricalCrossEntropy {
+    pub from_logits : bool,
+}
+impl CategoricalCrossEntropy {
+    pub fn with_logits() -> CategoricalCrossEntropy {
+        CategoricalCrossEntropy { from_logits: true }
+    }
+}
+impl LossFunction for CategoricalCrossEntropy {
+    fn evaluate(&self, output : &[f32], target : &[f32]) -> (f32, Vec<f32>) {
+        check_lengths(output, target);
+        if self.from_logits {
+            let lse = log_sum_exp(output);
+            let target_sum : f32 = target.iter().sum();
+            let loss = -output.iter().zip(target).map(|(z, t)| t * (z - lse)).sum::<f32>();
+            let gradient = output.iter().zip(target).map(|(z, t)| (z - lse).exp() * target_sum - t).collect();
+            (loss, gradient)
+        } else {
+            let mut loss = 0.0;
+            let mut gradient = Vec::with_capacity(output.len());
+            for (o, t) in output.iter().zip(target) {
+                let p = o.clamp(EPSILON, 1.0);
+                loss -= t * p.ln();
+                gradient.push(-t / p);
+            }
+            (loss, gradient)
+        }
+    }
+}
+
+/// Negative log likelihood of the target classes, for outputs that are log-probabilities
+/// such as those of a `LogSoftmaxLayer`. Together they are a numerically stable categorical
+/// cross entropy.
+///
+/// ```
+/// # use PotatoNeuralNet::{LossFunction, NegativeLogLikelihood};
+/// let (loss, gradient) = NegativeLogLikelihood.evaluate(&[-0.1, -2.5], &[1.0, 0.0]);
+/// assert_eq!(loss, 0.1);
+/// assert_eq!(gradient, vec![-1.0, 0.0]);
+/// ```
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NegativeLogLikelihood;
+impl LossFunction for NegativeLogLikelihood {
+    fn evaluate(&self, output : &[f32], target : &[f32]) -> (f32, Vec<f32>) {
+        check_lengths(output, target);
+        let loss = -output.iter().zip(target).map(|(y, t)| t * y).sum::<f32>();
+        (loss, target.iter().map(|t| -t).collect())
+    }
+}
+
+/// `ln(sum(e^x))`, shifted by the maximum so the exponentials can't overflow.
+pub(crate) fn log_sum_exp(values : &[f32]) -> f32 {
+    let max = values.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    if !max.is_finite() {
+        return max;
+    }
+    max + values.iter().map(|v| (v - max).exp()).sum::<f32>().ln()
+}
+
+/// Mean of `max(0, 1 - target * output)`, for targets of -1 or 1.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Hinge;
+impl LossFunction for Hinge {
+    fn evaluate(&self, output : &[f32], target : &[f32]) -> (f32, Vec<f32>) {
+        check_lengths(output, target);
+        let n = output.len().max(1) as f32;
+        let mut loss = 0.0;
+        let mut gradient = Vec::with_capacity(output.len());
+        for (o, t) in output.iter().zip(target) {
+            let margin = 1.0 - t * o;
+            if margin > 0.0 {
+                loss += margin;
+                gradient.push(-t / n);
+            } else {
+                gradient.push(0.0);
+            }
+        }
+        (loss / n, gradient)
+    }
+}
+
+/// Kullback-Leibler divergence of the output distribution from the target distribution.
+/// The outputs must be probabilities.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct KlDivergence;
+impl LossFunction for KlDivergence {
+    fn evaluate(&self, output : &[f32], target : &[f32]) -> (f32, Vec<f32>) {
+        check_lengths(output, target);
+        let mut loss = 0.0;
+        let mut gradient = Vec::with_capacity(output.len());
+        for (o, t) in output.iter().zip(target) {
+            let p = o.clamp(EPSILON, 1.0);
+            if *t > 0.0 {
+                loss += t * (t / p).ln();
+            }
+            gradient.push(-t / p);
+        }
+        (loss, gradient)
+    }
+}
@@ -0,0 +1,674 @@
+use crate::data_set::{Dataset, DatasetLoader, Datum, FileError, Split};
+use crate::layers::Layer;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+/// The outputs of a model for a set of samples, next to the class each sample belongs to.
+///
+/// Collect them with `predict`, or `record` them by hand, then compute metrics from them.
+/// Outputs wider than one are scores per class (probabilities, log-probabilities or logits);
+/// a single output is read as a regressed class index, so its class is the nearest one.
+///
+/// ```
+/// # use PotatoNeuralNet::Predictions;
+/// let mut predictions = Predictions::new();
+/// predictions.record(vec![0.1, 0.7, 0.2], 1);
+/// predictions.record(vec![0.5, 0.3, 0.2], 2);
+/// assert_eq!(predictions.accuracy(), 0.5);
+/// assert_eq!(predictions.top_k_accuracy(3), 1.0);
+/// println!("{}", predictions.report(2));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Predictions {
+    outputs : Vec<Vec<f32>>,
+    classes : Vec<usize>,
+}
+impl Predictions {
+    pub fn new() -> Predictions {
+        Predictions::default()
+    }
+    pub fn record(&mut self, output : Vec<f32>, class : usize) {
+        self.outputs.push(output);
+        self.classes.push(class);
+    }
+    pub fn len(&self) -> usize {
+        self.classes.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.classes.is_empty()
+    }
+    /// Number of classes: the width of the outputs, or more if a sample's class is beyond it.
+    pub fn class_count(&self) -> usize {
+        let width = self.outputs.iter().map(|o| if o.len() == 1 { 2 } else { o.len() }).max().unwrap_or(0);
+        let largest = self.classes.iter().map(|c| c + 1).max().unwrap_or(0);
+        width.max(largest)
+    }
+
+    /// A score per class, higher for more likely classes.
+    fn scores(output : &[f32], classes : usize) -> Vec<f32> {
+        match output {
+            [value] => (0..classes).map(|c| -(value - c as f32).abs()).collect(),
+            _ => (0..classes).map(|c| output.get(c).copied().unwrap_or(f32::NEG_INFINITY)).collect(),
+        }
+    }
+    fn predicted(output : &[f32], classes : usize) -> usize {
+        // NaN loses to everything, rather than winning as it would with `total_cmp`
+        let rank = |s : f32| if s.is_nan() { f32::NEG_INFINITY } else { s };
+        Self::scores(output, classes).iter().enumerate()
+            .max_by(|a, b| rank(*a.1).total_cmp(&rank(*b.1)))
+            .map(|(i, _)| i)
+            .unwrap_or(0)
+    }
+
+    /// Share of the samples whose highest scoring class is right. 0 without samples.
+    pub fn accuracy(&self) -> f32 {
+        self.top_k_accuracy(1)
+    }
+    /// Share of the samples whose class is among the `k` highest scoring ones. Ties count
+    /// in favour of the right class, a NaN score of the right class is a miss.
+    pub fn top_k_accuracy(&self, k : usize) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let classes = self.class_count();
+        let hits = self.outputs.iter().zip(&self.classes).filter(|(output, class)| {
+            let scores = Self::scores(output, classes);
+            let better = scores.iter().filter(|s| **s > scores[**class]).count();
+            !scores[**class].is_nan() && better < k
+        }).count();
+        hits as f32 / self.len() as f32
+    }
+    pub fn confusion_matrix(&self) -> ConfusionMatrix {
+        let classes = self.class_count();
+        let mut matrix = ConfusionMatrix::new(classes);
+        for (output, class) in self.outputs.iter().zip(&self.classes) {
+            matrix.record(*class, Self::predicted(output, classes));
+        }
+        matrix
+    }
+    /// Area under the ROC curve with class 1 as the positive class, for two-class problems.
+    /// `None` with more classes, when one of the classes has no samples, or when a score is
+    /// NaN, e.g. for a model that diverged.
+    ///
+    /// The score of a sample is its single output, or the second output minus the first.
+    ///
+    /// ```
+    /// # use PotatoNeuralNet::Predictions;
+    /// let mut predictions = Predictions::new();
+    /// predictions.record(vec![0.8], 1);
+    /// predictions.record(vec![0.2], 0);
+    /// assert_eq!(predictions.roc_auc(), Some(1.0));
+    /// predictions.record(vec![f32::NAN], 1);
+    /// assert_eq!(predictions.roc_auc(), None);
+    ///
+    /// let mut predictions = Predictions::new();
+    /// predictions.record(vec![0.8, 0.1], 0);
+    /// predictions.record(vec![], 1);
+    /// assert_eq!(predictions.roc_auc(), None);
+    /// ```
+    pub fn roc_auc(&self) -> Option<f32> {
+        if self.class_count() != 2 {
+            return None;
+        }
+        let mut scored : Vec<(f32, bool)> = self.outputs.iter().zip(&self.classes).map(|(output, class)| {
+            let score = match output.as_slice() {
+                [value] => *value,
+                [negative, positive, ..] => positive - negative,
+                [] => f32::NAN,
+            };
+            (score, *class == 1)
+        }).collect();
+        if scored.iter().any(|(score, _)| score.is_nan()) {
+            return None;
+        }
+        let positives = scored.iter().filter(|(_, positive)| *positive).count();
+        let negatives = scored.len() - positives;
+        if positives == 0 || negatives == 0 {
+            return None;
+        }
+        // Mann-Whitney U: the chance a random positive scores above a random negative,
+        // with tied scores sharing their average rank
+        scored.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut rank_sum = 0.0;
+        let mut i = 0;
+        while i < scored.len() {
+            let mut j = i;
+            while j < scored.len() && scored[j].0 == scored[i].0 {
+                j += 1;
+            }
+            let rank = (i + j + 1) as f64 / 2.0;
+            rank_sum += rank * scored[i..j].iter().filter(|(_, positive)| *positive).count() as f64;
+            i = j;
+        }
+        let u = rank_sum - (positives * (positives + 1)) as f64 / 2.0;
+        Some((u / (positives * negatives) as f64) as f32)
+    }
+    /// Every metric at once, with the top-`k` accuracy.
+    pub fn report(&self, k : usize) -> ClassificationReport {
+        let confusion = self.confusion_matrix();
+        ClassificationReport {
+            samples: self.len(),
+            accuracy: self.accuracy(),
+            top_k: k,
+            top_k_accuracy: self.top_k_accuracy(k),
+            classes: (0..confusion.classes).map(|c| confusion.class_metrics(c)).collect(),
+            macro_average: confusion.macro_average(),
+            micro_average: confusion.micro_average(),
+            roc_auc: self.roc_auc(),
+            class_names: None,
+            confusion,
+        }
+    }
+}
+
+/// Runs `layer` over every sample of `split` without updating it and collects its outputs.
+pub fn predict<L, D, Ld, const SIZE : usize>(
+    layer : &mut L,
+    input : Rc<RefCell<[f32; SIZE]>>,
+    data : &Dataset<D, Ld, SIZE>,
+    split : Split,
+) -> Predictions
+where
+    L : Layer,
+    D : Datum<SIZE, ReceiverType = Rc<RefCell<[f32; SIZE]>>>,
+    Ld : DatasetLoader<D, SIZE>,
+{
+    let mut predictions = Predictions::new();
+    for sample in data.get_samples(split) {
+        sample.get_data().seed(input.clone());
+        layer.calculate_state();
+        predictions.record(layer.get_values(), sample.get_class().round().max(0.0) as usize);
+    }
+    predictions
+}
+
+/// Counts of (actual, predicted) class pairs.
+///
+/// ```
+/// # use PotatoNeuralNet::ConfusionMatrix;
+/// let mut matrix = ConfusionMatrix::new(2);
+/// matrix.record(0, 0);
+/// matrix.record(1, 0);
+/// matrix.record(1, 1);
+/// assert_eq!(matrix.precision(0), 0.5);
+/// assert_eq!(matrix.recall(1), 0.5);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfusionMatrix {
+    classes : usize,
+    /// `counts[actual][predicted]`
+    counts : Vec<Vec<usize>>,
+}
+impl ConfusionMatrix {
+    pub fn new(classes : usize) -> ConfusionMatrix {
+        ConfusionMatrix { classes, counts: vec![vec![0; classes]; classes] }
+    }
+    /// Counts a sample, growing the matrix if either class is new.
+    pub fn record(&mut self, actual : usize, predicted : usize) {
+        let needed = actual.max(predicted) + 1;
+        if needed > self.classes {
+            self.counts.iter_mut().for_each(|row| row.resize(needed, 0));
+            self.counts.resize(needed, vec![0; needed]);
+            self.classes = needed;
+        }
+        self.counts[actual][predicted] += 1;
+    }
+    pub fn get_classes(&self) -> usize {
+        self.classes
+    }
+    pub fn get_count(&self, actual : usize, predicted : usize) -> usize {
+        self.counts.get(actual).and_then(|row| row.get(predicted)).copied().unwrap_or(0)
+    }
+    pub fn total(&self) -> usize {
+        self.counts.iter().flatten().sum()
+    }
+    fn true_positives(&self, class : usize) -> usize {
+        self.get_count(class, class)
+    }
+    fn predicted_as(&self, class : usize) -> usize {
+        self.counts.iter().map(|row| row[class]).sum()
+    }
+    /// Number of samples that belong to `class`.
+    pub fn support(&self, class : usize) -> usize {
+        self.counts.get(class).map_or(0, |row| row.iter().sum())
+    }
+    /// Share of the samples predicted as `class` that belong to it, 0 if none were.
+    pub fn precision(&self, class : usize) -> f32 {
+        ratio(self.true_positives(class), self.predicted_as(class))
+    }
+    /// Share of the samples of `class` predicted as it, 0 if there are none.
+    pub fn recall(&self, class : usize) -> f32 {
+        ratio(self.true_positives(class), self.support(class))
+    }
+    pub fn f1(&self, class : usize) -> f32 {
+        harmonic_mean(self.precision(class), self.recall(class))
+    }
+    pub fn class_metrics(&self, class : usize) -> ClassMetrics {
+        ClassMetrics {
+            precision: self.precision(class),
+            recall: self.recall(class),
+            f1: self.f1(class),
+            support: self.support(class),
+        }
+    }
+    /// Unweighted mean of the metrics of every class.
+    pub fn macro_average(&self) -> ClassMetrics {
+        let n = self.classes.max(1) as f32;
+        let all : Vec<ClassMetrics> = (0..self.classes).map(|c| self.class_metrics(c)).collect();
+        ClassMetrics {
+            precision: all.iter().map(|m| m.precision).sum::<f32>() / n,
+            recall: all.iter().map(|m| m.recall).sum::<f32>() / n,
+            f1: all.iter().map(|m| m.f1).sum::<f32>() / n,
+            support: self.total(),
+        }
+    }
+    /// Metrics of the pooled counts of every class. With one class per sample precision,
+    /// recall and F1 all equal the accuracy.
+    pub fn micro_average(&self) -> ClassMetrics {
+        let true_positives : usize = (0..self.classes).map(|c| self.true_positives(c)).sum();
+        let precision = ratio(true_positives, self.total());
+        let recall = ratio(true_positives, self.total());
+        ClassMetrics { precision, recall, f1: harmonic_mean(precision, recall), support: self.total() }
+    }
+    /// One row per actual class, one column per predicted class, with a header row.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("actual");
+        for c in 0..self.classes {
+            csv += &format!(",{c}");
+        }
+        for (actual, row) in self.counts.iter().enumerate() {
+            csv += &format!("\n{actual}");
+            for count in row {
+                csv += &format!(",{count}");
+            }
+        }
+        csv + "\n"
+    }
+}
+impl fmt::Display for ConfusionMatrix {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.counts.iter().flatten().max().copied().unwrap_or(0).max(self.classes).to_string().len().max(6) + 1;
+        write!(f, "{:>width$}", "actual")?;
+        for c in 0..self.classes {
+            write!(f, "{c:>width$}")?;
+        }
+        for (actual, row) in self.counts.iter().enumerate() {
+            write!(f, "\n{actual:>width$}")?;
+            for count in row {
+                write!(f, "{count:>width$}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Precision, recall and F1 of one class, or an average of them.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClassMetrics {
+    pub precision : f32,
+    pub recall : f32,
+    pub f1 : f32,
+    /// Number of samples the metrics cover.
+    pub support : usize,
+}
+
+/// The classification metrics of a set of predictions, see `Predictions::report`.
+///
+/// Prints as a table, and can be exported as CSV or JSON.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClassificationReport {
+    pub samples : usize,
+    pub accuracy : f32,
+    pub top_k : usize,
+    pub top_k_accuracy : f32,
+    /// The metrics of each class, by class index.
+    pub classes : Vec<ClassMetrics>,
+    pub macro_average : ClassMetrics,
+    pub micro_average : ClassMetrics,
+    /// Only for two classes, see `Predictions::roc_auc`.
+    pub roc_auc : Option<f32>,
+    pub confusion : ConfusionMatrix,
+    pub class_names : Option<Vec<String>>,
+}
+impl ClassificationReport {
+    /// Names the classes in the table and the CSV, e.g. with `ImageFolderLoader::get_classes`.
+    pub fn with_class_names(self, names : Vec<String>) -> ClassificationReport {
+        ClassificationReport { class_names: Some(names), ..self }
+    }
+    fn class_name(&self, class : usize) -> String {
+        self.class_names.as_ref().and_then(|names| names.get(class)).cloned().unwrap_or_else(|| class.to_string())
+    }
+    fn rows(&self) -> Vec<(String, ClassMetrics)> {
+        let mut rows : Vec<(String, ClassMetrics)> = self.classes.iter().enumerate().map(|(c, m)| (self.class_name(c), *m)).collect();
+        rows.push(("macro avg".into(), self.macro_average));
+        rows.push(("micro avg".into(), self.micro_average));
+        rows
+    }
+    /// The per-class table with the averages, as `class,precision,recall,f1,support` rows.
+    /// Class names are quoted where needed.
+    ///
+    /// ```
+    /// # use PotatoNeuralNet::Predictions;
+    /// let mut predictions = Predictions::new();
+    /// predictions.record(vec![0.9, 0.1], 0);
+    /// predictions.record(vec![0.2, 0.8], 1);
+    /// let report = predictions.report(1).with_class_names(vec!["cats, big".into(), "\"dogs\"".into()]);
+    /// let csv = report.to_csv();
+    /// let mut csv = csv::Reader::from_reader(csv.as_bytes());
+    /// let names : Vec<String> = csv.records().map(|row| row.unwrap()[0].to_string()).collect();
+    /// assert_eq!(names, ["cats, big", "\"dogs\"", "macro avg", "micro avg"]);
+    /// ```
+    pub fn to_csv(&self) -> String {
+        let mut csv = csv::Writer::from_writer(vec![]);
+        let header = ["class", "precision", "recall", "f1", "support"].map(String::from);
+        let rows = self.rows().into_iter()
+            .map(|(name, m)| [name, m.precision.to_string(), m.recall.to_string(), m.f1.to_string(), m.support.to_string()]);
+        for row in std::iter::once(header).chain(rows) {
+            csv.write_record(row).expect("writing to memory can't fail");
+        }
+        String::from_utf8(csv.into_inner().expect("writing to memory can't fail")).expect("the names and numbers are UTF-8")
+    }
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a report is always valid JSON")
+    }
+}
+impl fmt::Display for ClassificationReport {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rows = self.rows();
+        let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0).max(5);
+        writeln!(f, "{:<width$} {:>9} {:>9} {:>9} {:>9}", "class", "precision", "recall", "f1", "support")?;
+        for (name, m) in rows {
+            writeln!(f, "{name:<width$} {:>9.4} {:>9.4} {:>9.4} {:>9}", m.precision, m.recall, m.f1, m.support)?;
+        }
+        write!(f, "\naccuracy {:.4}, top-{} accuracy {:.4}", self.accuracy, self.top_k, self.top_k_accuracy)?;
+        if let Some(auc) = self.roc_auc {
+            write!(f, ", ROC-AUC {auc:.4}")?;
+        }
+        writeln!(f, " over {} samples", self.samples)?;
+        write!(f, "\nconfusion matrix (rows are actual, columns predicted classes)\n{}", self.confusion)
+    }
+}
+
+fn ratio(part : usize, whole : usize) -> f32 {
+    if whole == 0 { 0.0 } else { part as f32 / whole as f32 }
+}
+
+fn harmonic_mean(a : f32, b : f32) -> f32 {
+    if a + b == 0.0 { 0.0 } else { 2.0 * a * b / (a + b) }
+}
+
+/// The outputs of a regression model for a set of samples, next to their targets.
+///
+/// ```
+/// # use PotatoNeuralNet::RegressionPredictions;
+/// let mut predictions = RegressionPredictions::new();
+/// predictions.record(vec![2.5], vec![3.0]);
+/// predictions.record(vec![0.0], vec![-0.5]);
+/// predictions.record(vec![2.0], vec![2.0]);
+/// let metrics = predictions.metrics();
+/// assert!((metrics.mae - 1.0 / 3.0).abs() < 1e-6);
+/// println!("{}", predictions.report(8));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RegressionPredictions {
+    outputs : Vec<Vec<f32>>,
+    targets : Vec<Vec<f32>>,
+}
+impl RegressionPredictions {
+    pub fn new() -> RegressionPredictions {
+        RegressionPredictions::default()
+    }
+    /// Outputs past the end of `target`, or targets past the end of `output`, are ignored.
+    pub fn record(&mut self, output : Vec<f32>, target : Vec<f32>) {
+        self.outputs.push(output);
+        self.targets.push(target);
+    }
+    pub fn len(&self) -> usize {
+        self.targets.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+    /// Number of outputs of the model, the widest sample if they differ.
+    pub fn target_count(&self) -> usize {
+        self.outputs.iter().zip(&self.targets).map(|(o, t)| o.len().min(t.len())).max().unwrap_or(0)
+    }
+
+    /// (output, target) pairs of one target.
+    fn pairs(&self, target : usize) -> Vec<(f32, f32)> {
+        self.outputs.iter().zip(&self.targets)
+            .filter_map(|(o, t)| Some((*o.get(target)?, *t.get(target)?)))
+            .collect()
+    }
+    /// Output minus target of every sample for one target.
+    pub fn residuals(&self, target : usize) -> Vec<f32> {
+        self.pairs(target).iter().map(|(o, t)| o - t).collect()
+    }
+    /// The metrics of one target of a multi-output model.
+    pub fn target_metrics(&self, target : usize) -> RegressionMetrics {
+        RegressionMetrics::of(&self.pairs(target))
+    }
+    /// The metrics of every target pooled together. R² is the mean of the R² of each target,
+    /// since their variances aren't comparable.
+    pub fn metrics(&self) -> RegressionMetrics {
+        let pairs : Vec<(f32, f32)> = (0..self.target_count()).flat_map(|t| self.pairs(t)).collect();
+        let targets = self.target_count().max(1) as f32;
+        RegressionMetrics {
+            r2: (0..self.target_count()).map(|t| self.target_metrics(t).r2).sum::<f32>() / targets,
+            ..RegressionMetrics::of(&pairs)
+        }
+    }
+    /// Every metric at once, with residual histograms of `bins` bins.
+    pub fn report(&self, bins : usize) -> RegressionReport {
+        let targets = self.target_count();
+        let residuals : Vec<f32> = (0..targets).flat_map(|t| self.residuals(t)).collect();
+        RegressionReport {
+            samples: self.len(),
+            overall: self.metrics(),
+            targets: (0..targets).map(|t| self.target_metrics(t)).collect(),
+            residuals: Histogram::new(&residuals, bins),
+            target_residuals: (0..targets).map(|t| Histogram::new(&self.residuals(t), bins)).collect(),
+        }
+    }
+}
+
+/// Runs `layer` over every sample of `split` without updating it and collects its outputs,
+/// with the label of each sample as its target. Fails if the labels don't fit the outputs
+/// of `layer`, see `Dataset::check_labels`.
+pub fn predict_regression<L, D, Ld, const SIZE : usize>(
+    layer : &mut L,
+    input : Rc<RefCell<[f32; SIZE]>>,
+    data : &Dataset<D, Ld, SIZE>,
+    split : Split,
+) -> Result<RegressionPredictions, FileError>
+where
+    L : Layer,
+    D : Datum<SIZE, ReceiverType = Rc<RefCell<[f32; SIZE]>>>,
+    Ld : DatasetLoader<D, SIZE>,
+{
+    data.check_labels(layer.get_size())?;
+    let mut predictions = RegressionPredictions::new();
+    for sample in data.get_samples(split) {
+        sample.get_data().seed(input.clone());
+        layer.calculate_state();
+        let output = layer.get_values();
+        let target = sample.get_label().target(output.len()).expect("checked by check_labels");
+        predictions.record(output, target);
+    }
+    Ok(predictions)
+}
+
+/// Regression reports of both splits of `data`, with residual histograms of `bins` bins.
+pub fn evaluate_regression<L, D, Ld, const SIZE : usize>(
+    layer : &mut L,
+    input : Rc<RefCell<[f32; SIZE]>>,
+    data : &Dataset<D, Ld, SIZE>,
+    bins : usize,
+) -> Result<RegressionEvaluation, FileError>
+where
+    L : Layer,
+    D : Datum<SIZE, ReceiverType = Rc<RefCell<[f32; SIZE]>>>,
+    Ld : DatasetLoader<D, SIZE>,
+{
+    Ok(RegressionEvaluation {
+        training: predict_regression(layer, input.clone(), data, Split::Training)?.report(bins),
+        validation: predict_regression(layer, input, data, Split::Validation)?.report(bins),
+    })
+}
+
+/// Error metrics of a regression model.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RegressionMetrics {
+    /// Root mean squared error.
+    pub rmse : f32,
+    /// Mean absolute error.
+    pub mae : f32,
+    /// Coefficient of determination, 1 for a perfect fit and 0 for always predicting the mean.
+    pub r2 : f32,
+    /// Mean absolute percentage error, in percent. Targets of 0 are left out, so it is `None`
+    /// when every target is 0.
+    pub mape : Option<f32>,
+    /// Number of (output, target) pairs the metrics cover.
+    pub count : usize,
+}
+impl RegressionMetrics {
+    fn of(pairs : &[(f32, f32)]) -> RegressionMetrics {
+        if pairs.is_empty() {
+            return RegressionMetrics { rmse: 0.0, mae: 0.0, r2: 0.0, mape: None, count: 0 };
+        }
+        // accumulate in f64, sums of squares over a whole split lose precision in f32
+        let n = pairs.len() as f64;
+        let mean = pairs.iter().map(|(_, t)| *t as f64).sum::<f64>() / n;
+        let squared : f64 = pairs.iter().map(|(o, t)| (*o as f64 - *t as f64).powi(2)).sum();
+        let absolute : f64 = pairs.iter().map(|(o, t)| (*o as f64 - *t as f64).abs()).sum();
+        let variance : f64 = pairs.iter().map(|(_, t)| (*t as f64 - mean).powi(2)).sum();
+        let r2 = match (variance == 0.0, squared == 0.0) {
+            (false, _) => 1.0 - squared / variance,
+            // a constant target is only explained by predicting it exactly
+            (true, true) => 1.0,
+            (true, false) => 0.0,
+        };
+        let relative : Vec<f64> = pairs.iter().filter(|(_, t)| *t != 0.0)
+            .map(|(o, t)| ((*o as f64 - *t as f64) / *t as f64).abs())
+            .collect();
+        let mape = (!relative.is_empty()).then(|| (100.0 * relative.iter().sum::<f64>() / relative.len() as f64) as f32);
+        RegressionMetrics {
+            rmse: (squared / n).sqrt() as f32,
+            mae: (absolute / n) as f32,
+            r2: r2 as f32,
+            mape,
+            count: pairs.len(),
+        }
+    }
+}
+
+/// Counts of values in equal width bins between the smallest and largest of them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Histogram {
+    pub min : f32,
+    pub max : f32,
+    pub counts : Vec<usize>,
+}
+impl Histogram {
+    /// Sorts `values` into `bins` bins, at least one. Non-finite values are left out.
+    pub fn new(values : &[f32], bins : usize) -> Histogram {
+        let values : Vec<f32> = values.iter().copied().filter(|v| v.is_finite()).collect();
+        let bins = bins.max(1);
+        let mut min = values.iter().copied().fold(f32::INFINITY, f32::min);
+        let mut max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let mut counts = vec![0; bins];
+        if values.is_empty() {
+            return Histogram { min: 0.0, max: 0.0, counts };
+        }
+        if max - min <= 1e-6 * max.abs().max(1.0) {
+            // (nearly) equal values, centre them in a range of width 1 rather than rounding noise
+            min -= 0.5;
+            max += 0.5;
+        }
+        let width = (max - min) / bins as f32;
+        for v in values {
+            let bin = if width > 0.0 { ((v - min) / width) as usize } else { 0 };
+            counts[bin.min(bins - 1)] += 1;
+        }
+        Histogram { min, max, counts }
+    }
+    /// (start, end) of a bin.
+    pub fn bin_range(&self, bin : usize) -> (f32, f32) {
+        let width = (self.max - self.min) / self.counts.len() as f32;
+        (self.min + width * bin as f32, self.min + width * (bin + 1) as f32)
+    }
+}
+impl fmt::Display for Histogram {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        const BAR : usize = 40;
+        let largest = self.counts.iter().max().copied().unwrap_or(0).max(1);
+        for (bin, count) in self.counts.iter().enumerate() {
+            let (start, end) = self.bin_range(bin);
+            let bar = "#".repeat((count * BAR).div_ceil(largest));
+            writeln!(f, "[{start:>10.4}, {end:>10.4}) {count:>7} {bar}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The regression metrics of a set of predictions, see `RegressionPredictions::report`.
+///
+/// Prints as a table with the residual histogram, and can be exported as CSV or JSON.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RegressionReport {
+    pub samples : usize,
+    /// The metrics of every target pooled together.
+    pub overall : RegressionMetrics,
+    /// The metrics of each target, by output index.
+    pub targets : Vec<RegressionMetrics>,
+    /// Output minus target of every target pooled together.
+    pub residuals : Histogram,
+    pub target_residuals : Vec<Histogram>,
+}
+impl RegressionReport {
+    fn rows(&self) -> Vec<(String, RegressionMetrics)> {
+        let mut rows : Vec<(String, RegressionMetrics)> = Vec::new();
+        if self.targets.len() > 1 {
+            rows.extend(self.targets.iter().enumerate().map(|(t, m)| (t.to_string(), *m)));
+        }
+        rows.push(("overall".into(), self.overall));
+        rows
+    }
+    /// The per-target table, as `target,rmse,mae,r2,mape,count` rows. MAPE is empty when
+    /// it is undefined.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("target,rmse,mae,r2,mape,count\n");
+        for (name, m) in self.rows() {
+            let mape = m.mape.map(|v| v.to_string()).unwrap_or_default();
+            csv += &format!("{name},{},{},{},{mape},{}\n", m.rmse, m.mae, m.r2, m.count);
+        }
+        csv
+    }
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a report is always valid JSON")
+    }
+}
+impl fmt::Display for RegressionReport {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<7} {:>10} {:>10} {:>10} {:>10} {:>7}", "target", "rmse", "mae", "r2", "mape %", "count")?;
+        for (name, m) in self.rows() {
+            let mape = m.mape.map(|v| format!("{v:.4}")).unwrap_or_else(|| "-".into());
+            writeln!(f, "{name:<7} {:>10.4} {:>10.4} {:>10.4} {mape:>10} {:>7}", m.rmse, m.mae, m.r2, m.count)?;
+        }
+        write!(f, "\nresiduals over {} samples\n{}", self.samples, self.residuals)
+    }
+}
+
+/// Regression reports of the training and validation splits, see `evaluate_regression`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RegressionEvaluation {
+    pub training : RegressionReport,
+    pub validation : RegressionReport,
+}
+impl fmt::Display for RegressionEvaluation {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "training\n{}\nvalidation\n{}", self.training, self.validation)
+    }
+}
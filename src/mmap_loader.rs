@@ -0,0 +1,163 @@
+use crate::data_set::{ClassifiedData, DatasetLoader, Datum, FileError, RandomAccessLoader};
+use memmap2::Mmap;
+use std::fs::File;
+
+/// Where the classification of each record of a packed file comes from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PackedLabel {
+    /// A byte at this offset in every record.
+    Byte(usize),
+    /// One classification per record, e.g. read from a separate label file.
+    Given(Vec<f32>),
+}
+
+/// Describes a packed binary file of fixed-size records.
+///
+/// By default the file has no header and each record is a label byte followed by the
+/// data, which is the layout of the CIFAR-10 batches.
+///
+/// ```
+/// # use PotatoNeuralNet::PackedLayout;
+/// // the MNIST image file, with labels read beforehand
+/// # let labels = vec![0.0; 60000];
+/// let layout = PackedLayout::new(784).with_header(16).with_labels(labels);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct PackedLayout {
+    header : usize,
+    record_size : usize,
+    label : PackedLabel,
+    data : Option<(usize, usize)>,
+}
+impl PackedLayout {
+    pub fn new(record_size : usize) -> PackedLayout {
+        PackedLayout { header: 0, record_size, label: PackedLabel::Byte(0), data: None }
+    }
+    /// Skips `bytes` at the start of the file.
+    pub fn with_header(self, bytes : usize) -> PackedLayout {
+        PackedLayout { header: bytes, ..self }
+    }
+    /// Reads the classification from the byte at `offset` in each record.
+    pub fn with_label_byte(self, offset : usize) -> PackedLayout {
+        PackedLayout { label: PackedLabel::Byte(offset), ..self }
+    }
+    /// Uses these classifications, one per record, instead of reading them from the file.
+    /// Unless `with_data` says otherwise the whole record is then data.
+    pub fn with_labels(self, labels : Vec<f32>) -> PackedLayout {
+        PackedLayout { label: PackedLabel::Given(labels), ..self }
+    }
+    /// The data is the `len` bytes at `offset` in each record, instead of everything but the
+    /// label byte.
+    pub fn with_data(self, offset : usize, len : usize) -> PackedLayout {
+        PackedLayout { data: Some((offset, len)), ..self }
+    }
+
+    /// (offset, len) of the data in a record.
+    fn data_range(&self) -> (usize, usize) {
+        match (self.data, &self.label) {
+            (Some(range), _) => range,
+            (None, PackedLabel::Byte(0)) => (1, self.record_size.saturating_sub(1)),
+            // a label byte in the middle can't be cut out of one range, keep it in the data
+            (None, _) => (0, self.record_size),
+        }
+    }
+}
+
+/// Reads samples out of a memory-mapped packed binary file.
+///
+/// Only the pages that are touched are read by the operating system, so files much larger
+/// than memory can be used, especially with `Dataset::lazy`. The data of each record is
+/// passed to `Datum::from` as raw bytes.
+///
+/// ```no_run
+/// # use PotatoNeuralNet::{MmapLoader, PackedLayout};
+/// let loader = MmapLoader::new("./cifar/data_batch_1.bin", PackedLayout::new(3073)).unwrap();
+/// assert_eq!(loader.len(), 10000);
+/// ```
+pub struct MmapLoader {
+    path : String,
+    map : Mmap,
+    layout : PackedLayout,
+    records : usize,
+    current : usize,
+}
+impl MmapLoader {
+    /// Maps the file and checks that it holds whole records that fit the layout.
+    ///
+    /// ```
+    /// # use PotatoNeuralNet::{FileError, MmapLoader, PackedLayout};
+    /// let path = std::env::temp_dir().join(format!("potato_mmap_range_{}.bin", std::process::id()));
+    /// std::fs::write(&path, [0u8; 8]).unwrap();
+    /// let path = path.to_str().unwrap();
+    /// assert_eq!(MmapLoader::new(path, PackedLayout::new(4).with_data(1, 3)).unwrap().len(), 2);
+    /// let out_of_range = MmapLoader::new(path, PackedLayout::new(4).with_data(2, usize::MAX));
+    /// assert!(matches!(out_of_range, Err(FileError::IncorrectFormat(..))));
+    /// std::fs::remove_file(path).unwrap();
+    /// ```
+    pub fn new(path : &str, layout : PackedLayout) -> Result<MmapLoader, FileError> {
+        let file = File::open(path).map_err(|e| FileError::FileNotReadable(path.into(), e))?;
+        // SAFETY: the map is only read, and the file is expected to stay unchanged while it is
+        // used, like every other dataset file.
+        let map = unsafe { Mmap::map(&file) }.map_err(|e| FileError::FileNotReadable(path.into(), e))?;
+
+        let format = || FileError::IncorrectFormat(path.into(), format!("{} byte header and {} byte records", layout.header, layout.record_size));
+        if layout.record_size == 0 || map.len() < layout.header || !(map.len() - layout.header).is_multiple_of(layout.record_size) {
+            return Err(format());
+        }
+        let records = (map.len() - layout.header) / layout.record_size;
+        let (offset, len) = layout.data_range();
+        let label_in_range = match &layout.label {
+            PackedLabel::Byte(label) => *label < layout.record_size,
+            PackedLabel::Given(labels) if labels.len() != records => {
+                return Err(FileError::CountMismatch(path.into(), records, labels.len()));
+            }
+            PackedLabel::Given(_) => true,
+        };
+        if offset.checked_add(len).is_none_or(|end| end > layout.record_size) || !label_in_range {
+            return Err(format());
+        }
+        Ok(MmapLoader { path: path.into(), map, layout, records, current: 0 })
+    }
+    /// Number of records in the file.
+    pub fn len(&self) -> usize {
+        self.records
+    }
+    pub fn is_empty(&self) -> bool {
+        self.records == 0
+    }
+
+    fn record(&self, index : usize) -> &[u8] {
+        let start = self.layout.header + index * self.layout.record_size;
+        &self.map[start..start + self.layout.record_size]
+    }
+}
+impl <D : Datum<SIZE>, const SIZE : usize> DatasetLoader<D, SIZE> for MmapLoader {
+    fn next(&mut self) -> Result<ClassifiedData<D, SIZE>, FileError> {
+        self.current += 1;
+        self.load(self.current - 1)
+    }
+
+    fn has_next(&self) -> bool {
+        self.current < self.records
+    }
+}
+impl <D : Datum<SIZE>, const SIZE : usize> RandomAccessLoader<D, SIZE> for MmapLoader {
+    fn len(&self) -> usize {
+        self.records
+    }
+
+    fn get_class(&self, index : usize) -> f32 {
+        match &self.layout.label {
+            PackedLabel::Byte(offset) => self.record(index)[*offset] as f32,
+            PackedLabel::Given(labels) => labels[index],
+        }
+    }
+
+    fn load(&mut self, index : usize) -> Result<ClassifiedData<D, SIZE>, FileError> {
+        let (offset, len) = self.layout.data_range();
+        let data = self.record(index)[offset..offset + len].to_vec();
+        let class = RandomAccessLoader::<D, SIZE>::get_class(self, index);
+        D::from(data).map(|data| ClassifiedData::new(data, class))
+            .ok_or_else(|| FileError::SampleRejected(index, self.path.clone()))
+    }
+}
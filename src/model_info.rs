@@ -1,24 +1,198 @@
-#[derive(Copy, Clone)]
-#[allow(dead_code)]
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+/// How the learning rate evolves with the number of `ModelInformation::update` calls (steps).
+///
+/// Every schedule is a pure function of the step and the initial learning rate, except for
+/// `ReduceOnPlateau`, which is driven by `ModelInformation::report_validation_loss`.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LearningRateSchedule {
+    /// Keeps the initial learning rate.
+    Constant,
+    /// Multiplies the learning rate by `rate` every step.
+    Exponential { rate: f32 },
+    /// Multiplies the learning rate by `gamma` every `step_size` steps.
+    Step { step_size: usize, gamma: f32 },
+    /// Cosine annealing from the initial learning rate down to `min_lr` over `period` steps,
+    /// then restarting. Each period is `period_mult` times longer than the last.
+    CosineWarmRestarts { period: usize, period_mult: usize, min_lr: f32 },
+    /// Anneals up from `initial / div_factor` to the initial learning rate during the first
+    /// `pct_start` of `total_steps`, then down to `initial / (div_factor * final_div_factor)`.
+    OneCycle { total_steps: usize, pct_start: f32, div_factor: f32, final_div_factor: f32 },
+    /// Decays polynomially to `end_lr` over `total_steps`, then stays there.
+    Polynomial { total_steps: usize, power: f32, end_lr: f32 },
+    /// Keeps the learning rate until the validation loss stops improving by more than
+    /// `threshold` (relative) for more than `patience` reports, then multiplies it by `factor`.
+    ReduceOnPlateau { factor: f32, patience: usize, threshold: f32, min_lr: f32 },
+}
+impl LearningRateSchedule {
+    /// The learning rate at `step`, ignoring any plateau reductions. Takes the same time for
+    /// any step.
+    ///
+    /// ```
+    /// # use PotatoNeuralNet::LearningRateSchedule;
+    /// let schedule = LearningRateSchedule::CosineWarmRestarts { period: 10, period_mult: 2, min_lr: 0.0 };
+    /// // periods of 10, 20, 40, ... steps
+    /// assert_eq!(schedule.learning_rate(1.0, 30), 1.0);
+    /// assert!((schedule.learning_rate(1.0, 50) - 0.5).abs() < 1e-6);
+    /// assert_eq!(LearningRateSchedule::Exponential { rate: 0.5 }.learning_rate(1.0, 3_000_000_000), 0.0);
+    /// ```
+    pub fn learning_rate(&self, initial : f32, step : usize) -> f32 {
+        match *self {
+            LearningRateSchedule::Constant | LearningRateSchedule::ReduceOnPlateau { .. } => initial,
+            LearningRateSchedule::Exponential { rate } => initial * rate.powf(step as f32),
+            LearningRateSchedule::Step { step_size, gamma } => {
+                initial * gamma.powf((step / step_size.max(1)) as f32)
+            }
+            LearningRateSchedule::CosineWarmRestarts { period, period_mult, min_lr } => {
+                let (start, length) = restart(step, period, period_mult);
+                cosine(initial, min_lr, (step as u128 - start) as f32 / length as f32)
+            }
+            LearningRateSchedule::OneCycle { total_steps, pct_start, div_factor, final_div_factor } => {
+                let start = initial / div_factor;
+                let end = start / final_div_factor;
+                let warm = (total_steps as f32 * pct_start).max(1.0);
+                let step = step as f32;
+                if step < warm {
+                    cosine(start, initial, step / warm)
+                } else {
+                    let rest = (total_steps as f32 - warm).max(1.0);
+                    cosine(initial, end, ((step - warm) / rest).min(1.0))
+                }
+            }
+            LearningRateSchedule::Polynomial { total_steps, power, end_lr } => {
+                let progress = step.min(total_steps) as f32 / total_steps.max(1) as f32;
+                (initial - end_lr) * (1.0 - progress).powf(power) + end_lr
+            }
+        }
+    }
+}
+/// (first step, length) of the warm restart period that `step` is in.
+fn restart(step : usize, period : usize, period_mult : usize) -> (u128, u128) {
+    let (step, period, mult) = (step as u128, period.max(1) as u128, period_mult.max(1) as u128);
+    if mult == 1 {
+        return (step - step % period, period);
+    }
+    // period k starts at period * (mult^k - 1) / (mult - 1), so k is about a logarithm;
+    // saturating keeps the starts past any step, rather than wrapping
+    let start = |k : u32| period.saturating_mul(mult.saturating_pow(k) - 1) / (mult - 1);
+    let mut k = ((step * (mult - 1) / period + 1) as f64).log(mult as f64) as u32;
+    while k > 0 && start(k) > step {
+        k -= 1;
+    }
+    while start(k + 1) <= step {
+        k += 1;
+    }
+    (start(k), period.saturating_mul(mult.saturating_pow(k)))
+}
+/// Cosine interpolation from `from` (at 0) to `to` (at 1).
+fn cosine(from : f32, to : f32, progress : f32) -> f32 {
+    to + (from - to) * (1.0 + (PI * progress).cos()) / 2.0
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ModelInformation {
     //implementation detail
     learning_rate_initial : f32,
-    learning_rate_current : f32,
-    learning_rate_rate: f32
+    schedule : LearningRateSchedule,
+    // the learning rate rises linearly over these steps before the schedule starts
+    warmup_steps : usize,
+    step : usize,
+    // reduce on plateau state
+    plateau_scale : f32,
+    best_loss : Option<f32>,
+    bad_reports : usize,
 }
 impl ModelInformation {
+    /// Starts at `lr`, multiplying the learning rate by `dlr` every update.
     pub fn new(lr : f32, dlr : f32) -> ModelInformation {
-        ModelInformation { learning_rate_initial: lr, learning_rate_current: lr, learning_rate_rate: dlr }
+        ModelInformation::with_schedule(lr, LearningRateSchedule::Exponential { rate: dlr })
     }
-    #[allow(dead_code)]
-    pub fn update(self) -> ModelInformation {
+    pub fn with_schedule(lr : f32, schedule : LearningRateSchedule) -> ModelInformation {
         ModelInformation {
-            learning_rate_initial: self.learning_rate_initial,
-            learning_rate_current: self.learning_rate_current * self.learning_rate_rate,
-            learning_rate_rate: self.learning_rate_rate
+            learning_rate_initial: lr,
+            schedule,
+            warmup_steps: 0,
+            step: 0,
+            plateau_scale: 1.0,
+            best_loss: None,
+            bad_reports: 0,
+        }
+    }
+    /// Ramps the learning rate up linearly over the first `steps` updates. The schedule
+    /// starts counting once the warmup is over.
+    pub fn with_warmup(self, steps : usize) -> ModelInformation {
+        ModelInformation { warmup_steps: steps, ..self }
+    }
+    pub fn update(self) -> ModelInformation {
+        ModelInformation { step: self.step + 1, ..self }
+    }
+    /// Feeds the latest validation loss to a `ReduceOnPlateau` schedule. Other schedules
+    /// ignore it. A NaN or infinite loss counts as no improvement.
+    ///
+    /// ```
+    /// # use PotatoNeuralNet::{LearningRateSchedule, ModelInformation};
+    /// let schedule = LearningRateSchedule::ReduceOnPlateau { factor: 0.5, patience: 1, threshold: 0.0, min_lr: 0.0 };
+    /// let info = ModelInformation::with_schedule(1.0, schedule)
+    ///     .report_validation_loss(0.2)
+    ///     .report_validation_loss(f32::NAN)
+    ///     .report_validation_loss(0.3);
+    /// // the best loss is still 0.2, so that was a plateau
+    /// assert_eq!(info.get_lr(), 0.5);
+    /// ```
+    pub fn report_validation_loss(self, loss : f32) -> ModelInformation {
+        let LearningRateSchedule::ReduceOnPlateau { factor, patience, threshold, .. } = self.schedule else {
+            return self;
+        };
+        let mut next = self;
+        let improved = loss.is_finite() && self.best_loss.is_none_or(|best| loss < best * (1.0 - threshold));
+        if improved {
+            next.best_loss = Some(loss);
+            next.bad_reports = 0;
+        } else {
+            next.bad_reports += 1;
+            if next.bad_reports > patience {
+                next.plateau_scale *= factor;
+                next.bad_reports = 0;
+            }
         }
+        next
     }
     pub fn get_lr(&self)->f32 {
-        self.learning_rate_current
+        self.get_lr_at(self.step)
     }
-}
\ No newline at end of file
+    /// The learning rate this model will have after `step` updates, assuming no further
+    /// plateau reductions.
+    pub fn get_lr_at(&self, step : usize) -> f32 {
+        if step < self.warmup_steps {
+            return self.learning_rate_initial * (step + 1) as f32 / self.warmup_steps as f32;
+        }
+        let lr = self.schedule.learning_rate(self.learning_rate_initial, step - self.warmup_steps);
+        match self.schedule {
+            LearningRateSchedule::ReduceOnPlateau { min_lr, .. } => (lr * self.plateau_scale).max(min_lr),
+            _ => lr,
+        }
+    }
+    pub fn get_step(&self) -> usize {
+        self.step
+    }
+    pub fn get_schedule(&self) -> LearningRateSchedule {
+        self.schedule
+    }
+    /// Whether every number is finite, which JSON needs to write them.
+    pub(crate) fn is_finite(&self) -> bool {
+        let schedule = match self.schedule {
+            LearningRateSchedule::Constant => vec![],
+            LearningRateSchedule::Exponential { rate } => vec![rate],
+            LearningRateSchedule::Step { gamma, .. } => vec![gamma],
+            LearningRateSchedule::CosineWarmRestarts { min_lr, .. } => vec![min_lr],
+            LearningRateSchedule::OneCycle { pct_start, div_factor, final_div_factor, .. } => vec![pct_start, div_factor, final_div_factor],
+            LearningRateSchedule::Polynomial { power, end_lr, .. } => vec![power, end_lr],
+            LearningRateSchedule::ReduceOnPlateau { factor, threshold, min_lr, .. } => vec![factor, threshold, min_lr],
+        };
+        [self.learning_rate_initial, self.plateau_scale].into_iter()
+            .chain(self.best_loss)
+            .chain(schedule)
+            .all(f32::is_finite)
+    }
+}
@@ -1,5 +1,319 @@
+use crate::layers::Layer;
 use crate::model_info::ModelInformation;
+use crate::serialization::ModelError;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 
+/// Tells the optimizer what kind of parameters it is updating, so it can treat weights
+/// and biases differently (e.g. AdamW does not decay biases).
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ParameterKind {
+    Weight,
+    Bias,
+}
 
-pub struct Optimizer {
-}
\ No newline at end of file
+/// Turns accumulated gradients into parameter updates.
+///
+/// Layers hand their parameters to the optimizer one group at a time (a row of fibers, or
+/// the biases) from `Layer::update`. The optimizer keeps its per-parameter state in slots,
+/// which are assigned in the order the groups arrive during a step. Since a layer stack is
+/// always walked in the same order, each group gets the same slot every step.
+///
+/// ```
+/// # use PotatoNeuralNet::{Adam, ConnectedGenericLayer, InputLayer, Layer, Linear, ModelInformation, Optimizer};
+/// # use std::{cell::RefCell, rc::Rc};
+/// # let input = Rc::new(RefCell::new(InputLayer::new(Rc::new(RefCell::new([1.0; 4])))));
+/// let mut layer : ConnectedGenericLayer<_, Linear, 1, 4> = ConnectedGenericLayer::new(input);
+/// let mut optimizer = Adam::new();
+/// layer.calculate_state();
+/// layer.backpropagate(&[layer.get_value(0).unwrap()]);
+/// optimizer.update_layer(&mut layer, ModelInformation::new(0.01, 1.0));
+/// ```
+pub trait Optimizer {
+    /// Starts a new update pass over the layer stack.
+    fn begin_step(&mut self);
+    /// Updates one group of parameters in place using their gradients.
+    fn step(&mut self, params : &mut [f32], gradients : &[f32], kind : ParameterKind, learning_rate : f32);
+    /// The hyperparameters and per-parameter state, for checkpoints. Checkpoints refuse state
+    /// with nulls, which is how NaN and infinite numbers end up in JSON.
+    fn save_state(&self) -> serde_json::Value;
+    /// Restores the state produced by `save_state` of the same kind of optimizer.
+    fn load_state(&mut self, state : serde_json::Value) -> Result<(), ModelError>;
+
+    /// Applies the gradients accumulated in `layer` and every layer before it.
+    fn update_layer<L : Layer>(&mut self, layer : &mut L, info : ModelInformation) where Self : Sized {
+        self.begin_step();
+        layer.update(info, self);
+    }
+}
+
+fn save<T : Serialize>(optimizer : &T) -> serde_json::Value {
+    serde_json::to_value(optimizer).unwrap()
+}
+fn load<T : DeserializeOwned>(optimizer : &mut T, state : serde_json::Value) -> Result<(), ModelError> {
+    *optimizer = serde_json::from_value(state).map_err(|e| ModelError::Format(format!("optimizer state: {e}")))?;
+    Ok(())
+}
+
+/// Per-parameter buffers of an optimizer, `BUFFERS` of them for every slot.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(try_from = "SavedSlots", into = "SavedSlots")]
+struct SlotState<const BUFFERS : usize> {
+    slot : usize,
+    buffers : Vec<[Vec<f32>; BUFFERS]>,
+}
+impl <const BUFFERS : usize> SlotState<BUFFERS> {
+    fn begin_step(&mut self) {
+        self.slot = 0;
+    }
+    /// Buffers of the next slot, created zeroed the first time the slot is used.
+    ///
+    /// Panics if the slot was created for a different number of parameters, which means the
+    /// optimizer is used with another model, or its layers are updated in another order.
+    fn next(&mut self, len : usize) -> &mut [Vec<f32>; BUFFERS] {
+        if self.buffers.len() <= self.slot {
+            self.buffers.push(std::array::from_fn(|_| vec![0.0; len]));
+        }
+        let found = self.buffers[self.slot].first().map_or(len, Vec::len);
+        assert_eq!(found, len, "optimizer slot {} holds state for {found} parameters, not {len}: was the optimizer used with another model?", self.slot);
+        self.slot += 1;
+        &mut self.buffers[self.slot - 1]
+    }
+}
+// serde can't handle arrays of a generic length, so the buffers are saved as nested vectors
+#[derive(Serialize, Deserialize)]
+struct SavedSlots {
+    buffers : Vec<Vec<Vec<f32>>>,
+}
+impl <const BUFFERS : usize> From<SlotState<BUFFERS>> for SavedSlots {
+    fn from(state : SlotState<BUFFERS>) -> Self {
+        SavedSlots { buffers: state.buffers.into_iter().map(Vec::from).collect() }
+    }
+}
+impl <const BUFFERS : usize> TryFrom<SavedSlots> for SlotState<BUFFERS> {
+    type Error = String;
+    fn try_from(saved : SavedSlots) -> Result<Self, Self::Error> {
+        let buffers = saved.buffers.into_iter()
+            .map(|b| <[Vec<f32>; BUFFERS]>::try_from(b).map_err(|b| format!("expected {BUFFERS} buffers per slot, got {}", b.len())))
+            .collect::<Result<_, _>>()?;
+        Ok(SlotState { slot: 0, buffers })
+    }
+}
+
+/// Stochastic gradient descent, optionally with (Nesterov) momentum.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Sgd {
+    momentum : f32,
+    nesterov : bool,
+    state : SlotState<1>,
+}
+impl Sgd {
+    /// Plain gradient descent, `p -= lr * g`.
+    pub fn new() -> Sgd {
+        Sgd::default()
+    }
+    pub fn with_momentum(momentum : f32, nesterov : bool) -> Sgd {
+        Sgd { momentum, nesterov, state: SlotState::default() }
+    }
+}
+impl Optimizer for Sgd {
+    fn begin_step(&mut self) {
+        self.state.begin_step();
+    }
+    fn save_state(&self) -> serde_json::Value {
+        save(self)
+    }
+    fn load_state(&mut self, state : serde_json::Value) -> Result<(), ModelError> {
+        load(self, state)
+    }
+    fn step(&mut self, params : &mut [f32], gradients : &[f32], _kind : ParameterKind, learning_rate : f32) {
+        if self.momentum == 0.0 {
+            for (p, g) in params.iter_mut().zip(gradients) {
+                *p -= learning_rate * g;
+            }
+            return;
+        }
+        let [velocity] = self.state.next(params.len());
+        for ((p, g), v) in params.iter_mut().zip(gradients).zip(velocity.iter_mut()) {
+            *v = self.momentum * *v + g;
+            let direction = if self.nesterov { g + self.momentum * *v } else { *v };
+            *p -= learning_rate * direction;
+        }
+    }
+}
+
+/// Scales the step of each parameter by a running average of its squared gradients.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RmsProp {
+    decay : f32,
+    epsilon : f32,
+    state : SlotState<1>,
+}
+impl RmsProp {
+    /// RMSProp with a decay of 0.9.
+    pub fn new() -> RmsProp {
+        RmsProp::with_decay(0.9)
+    }
+    pub fn with_decay(decay : f32) -> RmsProp {
+        RmsProp { decay, epsilon: 1e-8, state: SlotState::default() }
+    }
+}
+impl Default for RmsProp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Optimizer for RmsProp {
+    fn begin_step(&mut self) {
+        self.state.begin_step();
+    }
+    fn save_state(&self) -> serde_json::Value {
+        save(self)
+    }
+    fn load_state(&mut self, state : serde_json::Value) -> Result<(), ModelError> {
+        load(self, state)
+    }
+    fn step(&mut self, params : &mut [f32], gradients : &[f32], _kind : ParameterKind, learning_rate : f32) {
+        let [square_avg] = self.state.next(params.len());
+        for ((p, g), s) in params.iter_mut().zip(gradients).zip(square_avg.iter_mut()) {
+            *s = self.decay * *s + (1.0 - self.decay) * g * g;
+            *p -= learning_rate * g / (s.sqrt() + self.epsilon);
+        }
+    }
+}
+
+/// Scales the step of each parameter by the sum of all its past squared gradients.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Adagrad {
+    epsilon : f32,
+    state : SlotState<1>,
+}
+impl Adagrad {
+    pub fn new() -> Adagrad {
+        Adagrad { epsilon: 1e-8, state: SlotState::default() }
+    }
+}
+impl Default for Adagrad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Optimizer for Adagrad {
+    fn begin_step(&mut self) {
+        self.state.begin_step();
+    }
+    fn save_state(&self) -> serde_json::Value {
+        save(self)
+    }
+    fn load_state(&mut self, state : serde_json::Value) -> Result<(), ModelError> {
+        load(self, state)
+    }
+    fn step(&mut self, params : &mut [f32], gradients : &[f32], _kind : ParameterKind, learning_rate : f32) {
+        let [square_sum] = self.state.next(params.len());
+        for ((p, g), s) in params.iter_mut().zip(gradients).zip(square_sum.iter_mut()) {
+            *s += g * g;
+            *p -= learning_rate * g / (s.sqrt() + self.epsilon);
+        }
+    }
+}
+
+/// Adaptive moment estimation, with the usual bias correction.
+///
+/// The moments belong to the model the optimizer was first used with:
+///
+/// ```should_panic
+/// # use PotatoNeuralNet::{Adam, Optimizer, ParameterKind};
+/// let mut adam = Adam::new();
+/// adam.begin_step();
+/// adam.step(&mut [1.0; 4], &[0.1; 4], ParameterKind::Weight, 0.01);
+/// adam.begin_step();
+/// // a group of another size in the same slot
+/// adam.step(&mut [1.0; 3], &[0.1; 3], ParameterKind::Weight, 0.01);
+/// ```
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Adam {
+    beta1 : f32,
+    beta2 : f32,
+    epsilon : f32,
+    // number of steps taken, for the bias correction
+    t : i32,
+    state : SlotState<2>,
+}
+impl Adam {
+    /// Adam with `beta1 = 0.9` and `beta2 = 0.999`.
+    pub fn new() -> Adam {
+        Adam::with_betas(0.9, 0.999)
+    }
+    pub fn with_betas(beta1 : f32, beta2 : f32) -> Adam {
+        Adam { beta1, beta2, epsilon: 1e-8, t: 0, state: SlotState::default() }
+    }
+}
+impl Default for Adam {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Optimizer for Adam {
+    fn begin_step(&mut self) {
+        self.state.begin_step();
+        self.t += 1;
+    }
+    fn save_state(&self) -> serde_json::Value {
+        save(self)
+    }
+    fn load_state(&mut self, state : serde_json::Value) -> Result<(), ModelError> {
+        load(self, state)
+    }
+    fn step(&mut self, params : &mut [f32], gradients : &[f32], _kind : ParameterKind, learning_rate : f32) {
+        let correction1 = 1.0 - self.beta1.powi(self.t.max(1));
+        let correction2 = 1.0 - self.beta2.powi(self.t.max(1));
+        let [m, v] = self.state.next(params.len());
+        for (((p, g), m), v) in params.iter_mut().zip(gradients).zip(m.iter_mut()).zip(v.iter_mut()) {
+            *m = self.beta1 * *m + (1.0 - self.beta1) * g;
+            *v = self.beta2 * *v + (1.0 - self.beta2) * g * g;
+            let m_hat = *m / correction1;
+            let v_hat = *v / correction2;
+            *p -= learning_rate * m_hat / (v_hat.sqrt() + self.epsilon);
+        }
+    }
+}
+
+/// Adam with decoupled weight decay. Biases are not decayed.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AdamW {
+    adam : Adam,
+    weight_decay : f32,
+}
+impl AdamW {
+    /// AdamW with the default Adam betas and a weight decay of 0.01.
+    pub fn new() -> AdamW {
+        AdamW::with_weight_decay(0.01)
+    }
+    pub fn with_weight_decay(weight_decay : f32) -> AdamW {
+        AdamW { adam: Adam::new(), weight_decay }
+    }
+}
+impl Default for AdamW {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Optimizer for AdamW {
+    fn begin_step(&mut self) {
+        self.adam.begin_step();
+    }
+    fn save_state(&self) -> serde_json::Value {
+        save(self)
+    }
+    fn load_state(&mut self, state : serde_json::Value) -> Result<(), ModelError> {
+        load(self, state)
+    }
+    fn step(&mut self, params : &mut [f32], gradients : &[f32], kind : ParameterKind, learning_rate : f32) {
+        if kind == ParameterKind::Weight {
+            for p in params.iter_mut() {
+                *p -= learning_rate * self.weight_decay * *p;
+            }
+        }
+        self.adam.step(params, gradients, kind, learning_rate);
+    }
+}
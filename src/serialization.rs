@@ -0,0 +1,261 @@
+use crate::layers::Layer;
+use crate::model_info::ModelInformation;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+/// Version of the model file layout, stored in every saved model.
+pub const MODEL_FORMAT_VERSION : u32 = 1;
+
+// start of every binary model file
+const BINARY_MAGIC : &[u8; 4] = b"PNNM";
+
+/// Architecture and parameters of one layer, as stored in a model file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LayerRecord {
+    Input {
+        size : usize,
+    },
+    Connected {
+        size : usize,
+        inputs : usize,
+        activation : String,
+        use_bias : bool,
+        fibers : Vec<Vec<f32>>,
+        biases : Vec<f32>,
+    },
+    Softmax {
+        size : usize,
+    },
+    LogSoftmax {
+        size : usize,
+    },
+}
+
+/// Format of a saved model.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ModelFormat {
+    /// Human readable, everything is JSON.
+    Json,
+    /// A JSON header describing the architecture, followed by the parameters as packed
+    /// little endian f32s. Much smaller and faster to read for large layers.
+    Binary,
+}
+
+#[derive(Debug)]
+pub enum ModelError {
+    Io(std::io::Error),
+    /// The file is not a model file, or is damaged.
+    Format(String),
+    /// The file was written by a newer, unsupported format version.
+    UnsupportedVersion(u32),
+    /// The saved layers don't match the layers being loaded into.
+    ArchitectureMismatch(String),
+    /// A layer, counted from the input layer, has NaN or infinite parameters, which JSON can't
+    /// hold. The binary format can.
+    NonFiniteParameters(usize),
+    /// Some other saved number, e.g. in the training history or the optimizer state, is NaN
+    /// or infinite, which JSON can't hold.
+    NonFiniteValues(String),
+}
+impl fmt::Display for ModelError {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelError::Io(e) => write!(f, "could not access the model file: {e}"),
+            ModelError::Format(msg) => write!(f, "invalid model file: {msg}"),
+            ModelError::UnsupportedVersion(v) => write!(f, "unsupported model format version {v} (expected at most {MODEL_FORMAT_VERSION})"),
+            ModelError::ArchitectureMismatch(msg) => write!(f, "model does not match the layers: {msg}"),
+            ModelError::NonFiniteParameters(layer) => write!(f, "layer {layer} has NaN or infinite parameters, which JSON can't hold, save the model in the binary format"),
+            ModelError::NonFiniteValues(what) => write!(f, "{what} has NaN or infinite values, which JSON can't hold"),
+        }
+    }
+}
+impl std::error::Error for ModelError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ModelError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl From<std::io::Error> for ModelError {
+    fn from(e : std::io::Error) -> Self {
+        ModelError::Io(e)
+    }
+}
+
+/// A whole layer stack with its `ModelInformation`, ready to be written out.
+///
+/// ```
+/// # use PotatoNeuralNet::{ConnectedGenericLayer, InputLayer, Linear, ModelFile, ModelInformation};
+/// # use std::{cell::RefCell, rc::Rc};
+/// # let input = Rc::new(RefCell::new(InputLayer::new(Rc::new(RefCell::new([0.0; 4])))));
+/// let layer : ConnectedGenericLayer<_, Linear, 2, 4> = ConnectedGenericLayer::new(input);
+/// let file = ModelFile::from_layer(&layer, ModelInformation::new(0.1, 1.0));
+///
+/// # let input = Rc::new(RefCell::new(InputLayer::new(Rc::new(RefCell::new([0.0; 4])))));
+/// let mut copy : ConnectedGenericLayer<_, Linear, 2, 4> = ConnectedGenericLayer::new(input);
+/// ModelFile::from_json(&file.to_json().unwrap()).unwrap().apply(&mut copy).unwrap();
+/// assert_eq!(layer.get_fibers(), copy.get_fibers());
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ModelFile {
+    pub version : u32,
+    pub info : ModelInformation,
+    /// From the input layer to the output layer.
+    pub layers : Vec<LayerRecord>,
+}
+impl ModelFile {
+    pub fn from_layer<L : Layer>(layer : &L, info : ModelInformation) -> ModelFile {
+        let mut layers = vec![];
+        layer.export(&mut layers);
+        ModelFile { version: MODEL_FORMAT_VERSION, info, layers }
+    }
+    /// Loads the saved parameters into `layer`, which must have the same architecture as the
+    /// saved one, and returns the saved `ModelInformation`.
+    pub fn apply<L : Layer>(&self, layer : &mut L) -> Result<ModelInformation, ModelError> {
+        layer.import(&self.layers)?;
+        Ok(self.info)
+    }
+
+    /// Fails if any parameter, or a number of the `ModelInformation`, is NaN or infinite: JSON
+    /// would write it as `null`, and the file couldn't be loaded back.
+    ///
+    /// ```
+    /// # use PotatoNeuralNet::{ConnectedGenericLayer, InputLayer, LayerRecord, Linear, ModelError, ModelFile, ModelInformation};
+    /// # use std::{cell::RefCell, rc::Rc};
+    /// # let input = Rc::new(RefCell::new(InputLayer::new(Rc::new(RefCell::new([0.0; 4])))));
+    /// let layer : ConnectedGenericLayer<_, Linear, 2, 4> = ConnectedGenericLayer::new(input);
+    /// let mut file = ModelFile::from_layer(&layer, ModelInformation::new(0.1, 1.0));
+    /// if let LayerRecord::Connected { biases, .. } = &mut file.layers[1] {
+    ///     biases[0] = f32::NAN;
+    /// }
+    /// assert!(matches!(file.to_json(), Err(ModelError::NonFiniteParameters(1))));
+    /// // the binary format keeps them
+    /// let copy = ModelFile::from_binary(&file.to_binary()).unwrap();
+    /// assert!(matches!(&copy.layers[1], LayerRecord::Connected { biases, .. } if biases[0].is_nan()));
+    /// ```
+    pub fn to_json(&self) -> Result<String, ModelError> {
+        self.check_finite()?;
+        Ok(serde_json::to_string(self).unwrap())
+    }
+    pub(crate) fn check_finite(&self) -> Result<(), ModelError> {
+        if !self.info.is_finite() {
+            return Err(ModelError::NonFiniteValues("the model information".into()));
+        }
+        for (index, record) in self.layers.iter().enumerate() {
+            if let LayerRecord::Connected { fibers, biases, .. } = record {
+                if !fibers.iter().flatten().chain(biases).all(|p| p.is_finite()) {
+                    return Err(ModelError::NonFiniteParameters(index));
+                }
+            }
+        }
+        Ok(())
+    }
+    pub fn from_json(json : &str) -> Result<ModelFile, ModelError> {
+        let file : ModelFile = serde_json::from_str(json).map_err(|e| ModelError::Format(e.to_string()))?;
+        file.check_version()?;
+        Ok(file)
+    }
+
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut params : Vec<f32> = vec![];
+        let mut header = self.clone();
+        for record in header.layers.iter_mut() {
+            if let LayerRecord::Connected { fibers, biases, .. } = record {
+                params.extend(fibers.iter().flatten());
+                params.extend(biases.iter());
+                // the shape is in the header, the values go in the packed section
+                fibers.clear();
+                biases.clear();
+            }
+        }
+        let header = serde_json::to_vec(&header).unwrap();
+        let mut out = Vec::with_capacity(16 + header.len() + params.len() * 4);
+        out.extend_from_slice(BINARY_MAGIC);
+        out.extend_from_slice(&self.version.to_le_bytes());
+        out.extend_from_slice(&(header.len() as u64).to_le_bytes());
+        out.extend_from_slice(&header);
+        for p in params {
+            out.extend_from_slice(&p.to_le_bytes());
+        }
+        out
+    }
+    pub fn from_binary(bytes : &[u8]) -> Result<ModelFile, ModelError> {
+        if bytes.len() < 16 || &bytes[0..4] != BINARY_MAGIC {
+            return Err(ModelError::Format("missing binary model header".into()));
+        }
+        let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        if version > MODEL_FORMAT_VERSION {
+            return Err(ModelError::UnsupportedVersion(version));
+        }
+        let header_len = u64::from_le_bytes(bytes[8..16].try_into().unwrap()) as usize;
+        let header_end = 16usize.checked_add(header_len).filter(|end| *end <= bytes.len())
+            .ok_or_else(|| ModelError::Format("truncated header".into()))?;
+        let mut file : ModelFile = serde_json::from_slice(&bytes[16..header_end])
+            .map_err(|e| ModelError::Format(e.to_string()))?;
+        let mut params = bytes[header_end..].chunks_exact(4).map(|c| f32::from_le_bytes(c.try_into().unwrap()));
+        for record in file.layers.iter_mut() {
+            if let LayerRecord::Connected { size, inputs, fibers, biases, .. } = record {
+                for _ in 0..*size {
+                    let row : Vec<f32> = params.by_ref().take(*inputs).collect();
+                    if row.len() != *inputs {
+                        return Err(ModelError::Format("truncated parameters".into()));
+                    }
+                    fibers.push(row);
+                }
+                biases.extend(params.by_ref().take(*size));
+                if biases.len() != *size {
+                    return Err(ModelError::Format("truncated parameters".into()));
+                }
+            }
+        }
+        if params.next().is_some() {
+            return Err(ModelError::Format("trailing data after the parameters".into()));
+        }
+        Ok(file)
+    }
+
+    /// Writes the model to `path`.
+    pub fn save<P : AsRef<Path>>(&self, path : P, format : ModelFormat) -> Result<(), ModelError> {
+        let mut file = File::create(path)?;
+        match format {
+            ModelFormat::Json => file.write_all(self.to_json()?.as_bytes())?,
+            ModelFormat::Binary => file.write_all(&self.to_binary())?,
+        }
+        Ok(())
+    }
+    /// Reads a model written by `save` in either format.
+    pub fn load<P : AsRef<Path>>(path : P) -> Result<ModelFile, ModelError> {
+        let mut bytes = vec![];
+        File::open(path)?.read_to_end(&mut bytes)?;
+        if bytes.starts_with(BINARY_MAGIC) {
+            ModelFile::from_binary(&bytes)
+        } else {
+            let json = std::str::from_utf8(&bytes).map_err(|e| ModelError::Format(e.to_string()))?;
+            ModelFile::from_json(json)
+        }
+    }
+
+    fn check_version(&self) -> Result<(), ModelError> {
+        if self.version > MODEL_FORMAT_VERSION {
+            Err(ModelError::UnsupportedVersion(self.version))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Saves the layer stack ending in `layer`, along with `info`.
+pub fn save_model<L : Layer, P : AsRef<Path>>(path : P, layer : &L, info : ModelInformation, format : ModelFormat) -> Result<(), ModelError> {
+    ModelFile::from_layer(layer, info).save(path, format)
+}
+
+/// Loads a model saved by `save_model` into an identical layer stack ending in `layer`, and
+/// returns the saved `ModelInformation`.
+pub fn load_model<L : Layer, P : AsRef<Path>>(path : P, layer : &mut L) -> Result<ModelInformation, ModelError> {
+    ModelFile::load(path)?.apply(layer)
+}
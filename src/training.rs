@@ -0,0 +1,226 @@
+use crate::data_set::{ClassifiedData, Dataset, DatasetLoader, Datum, FileError, Split};
+use crate::layers::Layer;
+use crate::loss::LossFunction;
+use crate::model_info::ModelInformation;
+use crate::optimizer::Optimizer;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Result of running a model over one split of a dataset without updating it.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Evaluation {
+    /// Mean loss per sample.
+    pub loss : f32,
+    /// Share of the samples whose predicted class matches the classification, see
+    /// `predicted_class`.
+    pub accuracy : f32,
+    pub samples : usize,
+}
+
+/// Training and validation results of one epoch.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EpochRecord {
+    pub epoch : usize,
+    pub learning_rate : f32,
+    /// Mean loss over the training samples while they were trained on.
+    pub training_loss : f32,
+    pub validation : Evaluation,
+}
+
+/// Per epoch record of a training run, to compare the training and validation loss.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TrainingHistory {
+    epochs : Vec<EpochRecord>,
+}
+impl TrainingHistory {
+    pub fn new() -> TrainingHistory {
+        TrainingHistory::default()
+    }
+    pub fn record(&mut self, record : EpochRecord) {
+        self.epochs.push(record);
+    }
+    pub fn get_epochs(&self) -> &Vec<EpochRecord> {
+        &self.epochs
+    }
+    /// The epoch with the lowest validation loss so far.
+    pub fn best_epoch(&self) -> Option<&EpochRecord> {
+        self.epochs.iter().filter(|r| r.validation.samples > 0)
+            .min_by(|a, b| a.validation.loss.total_cmp(&b.validation.loss))
+    }
+    /// True when the validation loss hasn't improved for the last `patience` epochs while
+    /// the training loss kept going down, the usual sign of overfitting.
+    pub fn is_overfitting(&self, patience : usize) -> bool {
+        let Some(best) = self.best_epoch() else { return false; };
+        let Some(last) = self.epochs.last() else { return false; };
+        last.epoch >= best.epoch + patience && last.training_loss < best.training_loss
+    }
+}
+
+/// The vector a model is trained to output for `classification`: the classification itself
+/// for single output models, a one-hot vector of the class otherwise. `None` if the class is
+/// negative, fractional or not below `width`.
+///
+/// ```
+/// # use PotatoNeuralNet::target_vector;
+/// assert_eq!(target_vector(1.0, 3), Some(vec![0.0, 1.0, 0.0]));
+/// assert_eq!(target_vector(0.5, 1), Some(vec![0.5]));
+/// assert_eq!(target_vector(3.0, 3), None);
+/// assert_eq!(target_vector(-1.0, 3), None);
+/// assert_eq!(target_vector(0.5, 3), None);
+/// ```
+pub fn target_vector(classification : f32, width : usize) -> Option<Vec<f32>> {
+    if width == 1 {
+        return Some(vec![classification]);
+    }
+    let mut target = vec![0.0; width];
+    target[class_index(classification, width)?] = 1.0;
+    Some(target)
+}
+
+/// `classification` as a class index, if it is a whole number below `classes`.
+pub(crate) fn class_index(classification : f32, classes : usize) -> Option<usize> {
+    let valid = classification >= 0.0 && classification.fract() == 0.0 && classification < classes as f32;
+    valid.then_some(classification as usize)
+}
+
+/// The class a model predicts: the rounded output of single output models, the index of the
+/// largest output otherwise.
+pub fn predicted_class(output : &[f32]) -> f32 {
+    if output.len() == 1 {
+        output[0].round()
+    } else {
+        output.iter().enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(i, _)| i as f32)
+            .unwrap_or(0.0)
+    }
+}
+
+/// Seeds the input and runs the model forward, returning its output.
+fn forward<L, D, const SIZE : usize>(layer : &mut L, input : &Rc<RefCell<[f32; SIZE]>>, sample : &ClassifiedData<D, SIZE>) -> Vec<f32>
+where
+    L : Layer,
+    D : Datum<SIZE, ReceiverType = Rc<RefCell<[f32; SIZE]>>>,
+{
+    sample.get_data().seed(input.clone());
+    layer.calculate_state();
+    layer.get_values()
+}
+
+/// Trains `layer` on one epoch of shuffled mini-batches and returns the mean training loss.
+///
+/// `input` is the cell the `InputLayer` of the model reads from. Fails before training if
+/// the labels don't fit the outputs of `layer`, see `Dataset::check_labels`.
+pub fn train_epoch<L, D, Ld, O, const SIZE : usize>(
+    layer : &mut L,
+    input : Rc<RefCell<[f32; SIZE]>>,
+    data : &Dataset<D, Ld, SIZE>,
+    loss : &dyn LossFunction,
+    optimizer : &mut O,
+    info : ModelInformation,
+    batch_size : usize,
+) -> Result<f32, FileError>
+where
+    L : Layer,
+    D : Datum<SIZE, ReceiverType = Rc<RefCell<[f32; SIZE]>>>,
+    Ld : DatasetLoader<D, SIZE>,
+    O : Optimizer,
+{
+    train_epoch_with(layer, input, data, loss, optimizer, info, batch_size, |_, _| {})
+}
+
+/// `train_epoch`, calling `on_step` after every optimizer step with the index of the batch
+/// in the epoch and its mean loss, e.g. to log it with a `TrainingLogger`.
+#[allow(clippy::too_many_arguments)]
+pub fn train_epoch_with<L, D, Ld, O, F, const SIZE : usize>(
+    layer : &mut L,
+    input : Rc<RefCell<[f32; SIZE]>>,
+    data : &Dataset<D, Ld, SIZE>,
+    loss : &dyn LossFunction,
+    optimizer : &mut O,
+    info : ModelInformation,
+    batch_size : usize,
+    mut on_step : F,
+) -> Result<f32, FileError>
+where
+    L : Layer,
+    D : Datum<SIZE, ReceiverType = Rc<RefCell<[f32; SIZE]>>>,
+    Ld : DatasetLoader<D, SIZE>,
+    O : Optimizer,
+    F : FnMut(usize, f32),
+{
+    data.check_labels(layer.get_size())?;
+    let mut total = 0.0;
+    let mut amt = 0;
+    for (index, batch) in data.get_training_batches(batch_size, false, true).enumerate() {
+        let mut batch_total = 0.0;
+        let mut batch_amt = 0;
+        for sample in batch {
+            let output = forward(layer, &input, &sample);
+            let target = sample.get_label().target(output.len()).expect("checked by check_labels");
+            let (error, gradient) = loss.evaluate(&output, &target);
+            layer.backpropagate(&gradient);
+            batch_total += error;
+            batch_amt += 1;
+        }
+        optimizer.update_layer(layer, info);
+        if batch_amt > 0 {
+            on_step(index, batch_total / batch_amt as f32);
+        }
+        total += batch_total;
+        amt += batch_amt;
+    }
+    Ok(if amt == 0 { 0.0 } else { total / amt as f32 })
+}
+
+/// Runs `layer` over every sample of `split` without updating it. An empty split
+/// evaluates to all zeroes. Fails if the labels don't fit the outputs of `layer`.
+pub fn evaluate_split<L, D, Ld, const SIZE : usize>(
+    layer : &mut L,
+    input : Rc<RefCell<[f32; SIZE]>>,
+    data : &Dataset<D, Ld, SIZE>,
+    loss : &dyn LossFunction,
+    split : Split,
+) -> Result<Evaluation, FileError>
+where
+    L : Layer,
+    D : Datum<SIZE, ReceiverType = Rc<RefCell<[f32; SIZE]>>>,
+    Ld : DatasetLoader<D, SIZE>,
+{
+    data.check_labels(layer.get_size())?;
+    let mut total = 0.0;
+    let mut correct = 0;
+    let mut samples = 0;
+    for sample in data.get_samples(split) {
+        let output = forward(layer, &input, &sample);
+        total += loss.loss(&output, &sample.get_label().target(output.len()).expect("checked by check_labels"));
+        if sample.get_label().matches(&output) {
+            correct += 1;
+        }
+        samples += 1;
+    }
+    if samples == 0 {
+        return Ok(Evaluation { loss: 0.0, accuracy: 0.0, samples: 0 });
+    }
+    Ok(Evaluation {
+        loss: total / samples as f32,
+        accuracy: correct as f32 / samples as f32,
+        samples,
+    })
+}
+
+/// Runs `layer` over the validation split without updating it.
+pub fn evaluate<L, D, Ld, const SIZE : usize>(
+    layer : &mut L,
+    input : Rc<RefCell<[f32; SIZE]>>,
+    data : &Dataset<D, Ld, SIZE>,
+    loss : &dyn LossFunction,
+) -> Result<Evaluation, FileError>
+where
+    L : Layer,
+    D : Datum<SIZE, ReceiverType = Rc<RefCell<[f32; SIZE]>>>,
+    Ld : DatasetLoader<D, SIZE>,
+{
+    evaluate_split(layer, input, data, loss, Split::Validation)
+}
@@ -0,0 +1,236 @@
+use crate::training::EpochRecord;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// How a `TrainingLogger` writes its records.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    /// One row per record under a header row, with a column per metric.
+    Csv,
+    /// One JSON object per line.
+    JsonLines,
+}
+impl LogFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            LogFormat::Csv => "csv",
+            LogFormat::JsonLines => "jsonl",
+        }
+    }
+}
+
+/// One line of a training log, for a training step or a whole epoch.
+///
+/// `timestamp` and `elapsed` are filled in by `TrainingLogger::log`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LogRecord {
+    /// Seconds since the Unix epoch.
+    pub timestamp : f64,
+    /// Wall-clock seconds spent on the run, counting earlier runs this log was resumed from.
+    pub elapsed : f64,
+    pub epoch : usize,
+    /// The batch of step records within their epoch, `None` for epoch records.
+    pub step : Option<usize>,
+    pub training_loss : Option<f32>,
+    pub validation_loss : Option<f32>,
+    pub learning_rate : f32,
+    pub metrics : BTreeMap<String, f32>,
+}
+impl LogRecord {
+    /// A record of one optimizer step, the `step`-th batch of `epoch`, with the mean loss of
+    /// the batch. Fits the callback of `train_epoch_with`.
+    pub fn step(epoch : usize, step : usize, training_loss : f32, learning_rate : f32) -> LogRecord {
+        LogRecord {
+            timestamp: 0.0,
+            elapsed: 0.0,
+            epoch,
+            step: Some(step),
+            training_loss: Some(training_loss),
+            validation_loss: None,
+            learning_rate,
+            metrics: BTreeMap::new(),
+        }
+    }
+    /// A record of a whole epoch, with the validation accuracy as the `validation_accuracy`
+    /// metric when there was a validation split.
+    pub fn epoch(record : &EpochRecord) -> LogRecord {
+        let mut metrics = BTreeMap::new();
+        if record.validation.samples > 0 {
+            metrics.insert("validation_accuracy".into(), record.validation.accuracy);
+        }
+        LogRecord {
+            timestamp: 0.0,
+            elapsed: 0.0,
+            epoch: record.epoch,
+            step: None,
+            training_loss: Some(record.training_loss),
+            validation_loss: (record.validation.samples > 0).then_some(record.validation.loss),
+            learning_rate: record.learning_rate,
+            metrics,
+        }
+    }
+    pub fn with_metric(mut self, name : &str, value : f32) -> LogRecord {
+        self.metrics.insert(name.into(), value);
+        self
+    }
+}
+
+/// Streams training records to `<dir>/<run name>.csv` or `.jsonl` while training runs.
+///
+/// Every record is flushed as it is logged, so a crash loses nothing that was logged before
+/// it. Logging into an existing run appends to it, e.g. after resuming from a checkpoint, and
+/// the elapsed time carries on from its last record.
+///
+/// ```no_run
+/// # use PotatoNeuralNet::{LogFormat, LogRecord, TrainingLogger};
+/// let mut logger = TrainingLogger::new("./logs", "baseline", LogFormat::Csv).unwrap()
+///     .with_metric_columns(&["validation_accuracy", "rmse"])
+///     .with_step_interval(10);
+/// logger.log(LogRecord::step(0, 0, 0.52, 0.001)).unwrap();
+/// ```
+pub struct TrainingLogger {
+    path : PathBuf,
+    writer : BufWriter<File>,
+    format : LogFormat,
+    metric_columns : Vec<String>,
+    step_interval : usize,
+    header_written : bool,
+    start : Instant,
+    // elapsed time of the records already in the log
+    resumed_at : f64,
+}
+impl TrainingLogger {
+    /// Logs into `<dir>/<run_name>` with the extension of `format`, creating `dir` if needed.
+    /// The run name must be a plain file name, without path separators.
+    ///
+    /// When the log exists, the elapsed time carries on from its last record that has one,
+    /// even if later records have a NaN loss or were cut short:
+    ///
+    /// ```
+    /// # use PotatoNeuralNet::{LogFormat, LogRecord, TrainingLogger};
+    /// let dir = std::env::temp_dir().join(format!("potato_log_resume_{}", std::process::id()));
+    /// std::fs::create_dir_all(&dir).unwrap();
+    /// std::fs::write(dir.join("diverged.jsonl"), concat!(
+    ///     r#"{"timestamp":1.0,"elapsed":120.0,"epoch":3,"step":null,"training_loss":0.5,"validation_loss":null,"learning_rate":0.1,"metrics":{}}"#, "\n",
+    ///     r#"{"timestamp":2.0,"elapsed":130.0,"epoch":4,"step":null,"training_loss":null,"validation_loss":null,"learning_rate":null,"metrics":{"rmse":null}}"#, "\n",
+    ///     r#"{"timestamp":3.0,"elapsed":14"#,
+    /// )).unwrap();
+    /// let mut logger = TrainingLogger::new(&dir, "diverged", LogFormat::JsonLines).unwrap();
+    /// logger.log(LogRecord::step(5, 0, f32::NAN, 0.1)).unwrap();
+    /// let log = std::fs::read_to_string(logger.get_path()).unwrap();
+    /// let last : serde_json::Value = serde_json::from_str(log.lines().last().unwrap()).unwrap();
+    /// assert!(last["elapsed"].as_f64().unwrap() >= 130.0);
+    ///
+    /// // metric names are quoted in the CSV header where needed
+    /// let mut logger = TrainingLogger::new(&dir, "quoted", LogFormat::Csv).unwrap()
+    ///     .with_metric_columns(&["top, 5"]);
+    /// logger.log(LogRecord::step(0, 0, 0.5, 0.1).with_metric("top, 5", 0.75)).unwrap();
+    /// let mut csv = csv::Reader::from_path(logger.get_path()).unwrap();
+    /// assert_eq!(&csv.headers().unwrap()[7], "top, 5");
+    /// let row = csv.records().next().unwrap().unwrap();
+    /// assert_eq!((&row[4], &row[7]), ("0.5", "0.75"));
+    /// std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn new<P : AsRef<Path>>(dir : P, run_name : &str, format : LogFormat) -> io::Result<TrainingLogger> {
+        let plain = Path::new(run_name).file_name().is_some_and(|name| name == run_name);
+        if !plain || run_name.contains(['/', '\\']) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{run_name:?} is not a valid run name, it must be a file name")));
+        }
+        fs::create_dir_all(&dir)?;
+        let path = dir.as_ref().join(format!("{run_name}.{}", format.extension()));
+        let (resumed_at, cut_short) = match fs::read_to_string(&path) {
+            Ok(log) => (last_elapsed(&log, format), !log.is_empty() && !log.ends_with('\n')),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (0.0, false),
+            Err(e) => return Err(e),
+        };
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        // a crash mid-record leaves the last line open, new records start on their own
+        if cut_short {
+            writeln!(file)?;
+        }
+        let header_written = file.metadata()?.len() > 0;
+        Ok(TrainingLogger {
+            path,
+            writer: BufWriter::new(file),
+            format,
+            metric_columns: vec!["validation_accuracy".into()],
+            step_interval: 1,
+            header_written,
+            start: Instant::now(),
+            resumed_at,
+        })
+    }
+    /// The metrics that get a CSV column, `validation_accuracy` by default. Other metrics are
+    /// only kept in JSON Lines logs. When appending to an existing CSV log, use the columns it
+    /// was started with, its header is not rewritten.
+    pub fn with_metric_columns(self, names : &[&str]) -> TrainingLogger {
+        TrainingLogger { metric_columns: names.iter().map(|n| n.to_string()).collect(), ..self }
+    }
+    /// Only logs every `steps`-th step record, or none with 0. Epoch records are always logged.
+    pub fn with_step_interval(self, steps : usize) -> TrainingLogger {
+        TrainingLogger { step_interval: steps, ..self }
+    }
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Stamps `record` with the current time and writes it.
+    pub fn log(&mut self, mut record : LogRecord) -> io::Result<()> {
+        if let Some(step) = record.step {
+            if self.step_interval == 0 || !step.is_multiple_of(self.step_interval) {
+                return Ok(());
+            }
+        }
+        record.timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0.0, |d| d.as_secs_f64());
+        record.elapsed = self.resumed_at + self.start.elapsed().as_secs_f64();
+        match self.format {
+            LogFormat::JsonLines => {
+                serde_json::to_writer(&mut self.writer, &record)?;
+                writeln!(self.writer)?;
+            }
+            LogFormat::Csv => {
+                let mut csv = csv::Writer::from_writer(&mut self.writer);
+                if !self.header_written {
+                    let header = ["timestamp", "elapsed", "epoch", "step", "training_loss", "validation_loss", "learning_rate"];
+                    csv.write_record(header.into_iter().chain(self.metric_columns.iter().map(String::as_str)))?;
+                    self.header_written = true;
+                }
+                let optional = |value : Option<String>| value.unwrap_or_default();
+                let mut row = vec![
+                    format!("{:.3}", record.timestamp),
+                    format!("{:.3}", record.elapsed),
+                    record.epoch.to_string(),
+                    optional(record.step.map(|s| s.to_string())),
+                    optional(record.training_loss.map(|l| l.to_string())),
+                    optional(record.validation_loss.map(|l| l.to_string())),
+                    record.learning_rate.to_string(),
+                ];
+                row.extend(self.metric_columns.iter().map(|metric| optional(record.metrics.get(metric).map(|m| m.to_string()))));
+                csv.write_record(row)?;
+                csv.flush()?;
+            }
+        }
+        self.writer.flush()
+    }
+}
+
+/// The elapsed time of the last record of a log that has one, 0 if none does.
+///
+/// Only the `elapsed` field is read, a record with a NaN loss (written as `null` in JSON) or
+/// a line cut short by a crash doesn't reset the clock.
+fn last_elapsed(log : &str, format : LogFormat) -> f64 {
+    let elapsed = match format {
+        LogFormat::JsonLines => log.lines().rev().find_map(|line| {
+            serde_json::from_str::<serde_json::Value>(line).ok()?.get("elapsed")?.as_f64()
+        }),
+        LogFormat::Csv => csv::ReaderBuilder::new().flexible(true).from_reader(log.as_bytes())
+            .records()
+            .filter_map(|record| record.ok()?.get(1)?.parse().ok())
+            .last(),
+    };
+    elapsed.unwrap_or(0.0)
+}